@@ -0,0 +1,331 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use crate::clock::{Clock, SystemClock};
+use crate::connector::Connector;
+use crate::dedup::Window;
+use crate::stream::Incoming;
+
+/// CacheStats describes the usage of a ResponseCache
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    /// Number of responses currently cached
+    pub entries: usize,
+    /// Incoming requests answered from the cache
+    pub hits: u64,
+    /// Incoming requests passed through to be handled
+    pub misses: u64,
+    /// Entries evicted on expiry of the TTL
+    pub expired: u64,
+    /// Entries evicted to remain within the cache capacity
+    pub evicted: u64,
+}
+
+struct Inner<ReqId, Target, Resp, Ctx> {
+    window: Window<(Target, ReqId), (Resp, Ctx)>,
+    stats: CacheStats,
+    clock: Arc<dyn Clock>,
+}
+
+impl<ReqId, Target, Resp, Ctx> Inner<ReqId, Target, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Clone,
+    Target: Eq + Hash + Clone,
+{
+    fn insert(&mut self, key: (Target, ReqId), resp: Resp, ctx: Ctx) {
+        let now = self.clock.now();
+        let evictions = self.window.insert(key, (resp, ctx), now);
+        self.update(evictions);
+    }
+
+    fn expire(&mut self) {
+        let now = self.clock.now();
+        let evictions = self.window.expire(now);
+        self.update(evictions);
+    }
+
+    fn update(&mut self, (expired, evicted): (usize, usize)) {
+        self.stats.expired += expired as u64;
+        self.stats.evicted += evicted as u64;
+        self.stats.entries = self.window.len();
+    }
+}
+
+/// ResponseCache wraps a connector, storing the last response sent for each (Target, ReqId) so that
+/// retransmitted requests are answered with the same response rather than being handled again.
+/// Responses are held for a TTL with a bounded number of entries, and only requests for which a response
+/// has already been sent are answered from the cache.
+pub struct ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn> {
+    conn: Conn,
+    inner: Arc<Mutex<Inner<ReqId, Target, Resp, Ctx>>>,
+    resend: Option<BoxFuture<'static, Result<(), E>>>,
+
+    _req: PhantomData<Req>,
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Clone for ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    Conn: Clone,
+{
+    fn clone(&self) -> Self {
+        ResponseCache {
+            conn: self.conn.clone(),
+            inner: self.inner.clone(),
+            resend: None,
+            _req: PhantomData,
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Unpin for ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn> {}
+
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    ReqId: Eq + Hash + Clone,
+    Target: Eq + Hash + Clone,
+{
+    /// Create a new response cache wrapping the provided connector.
+    /// By default responses are held for 30 seconds, with a maximum of 1024 entries
+    pub fn new(conn: Conn) -> Self {
+        ResponseCache {
+            conn,
+            inner: Arc::new(Mutex::new(Inner {
+                window: Window::new(Duration::from_secs(30), 1024),
+                stats: CacheStats::default(),
+                clock: Arc::new(SystemClock),
+            })),
+            resend: None,
+            _req: PhantomData,
+        }
+    }
+
+    /// Set the duration for which responses are cached
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        self.inner.lock().unwrap().window.set_ttl(ttl);
+        self
+    }
+
+    /// Set the maximum number of cached responses
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        self.inner.lock().unwrap().window.set_capacity(capacity);
+        self
+    }
+
+    /// Set the clock used to age cached responses
+    pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.inner.lock().unwrap().clock = Arc::new(clock);
+        self
+    }
+
+    /// Fetch cache statistics
+    pub fn stats(&self) -> CacheStats {
+        let mut inner = self.inner.lock().unwrap();
+        inner.expire();
+        inner.stats.clone()
+    }
+
+    /// Remove all cached responses
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.window.clear();
+        inner.stats.entries = 0;
+    }
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Connector<ReqId, Target, Req, Resp, E, Ctx>
+    for ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Clone + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + Send + 'static,
+    Conn: Connector<ReqId, Target, Req, Resp, E, Ctx> + Send + 'static,
+{
+    /// Send a request via the underlying connector
+    async fn request(&mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req) -> Result<Resp, E> {
+        self.conn.request(ctx, req_id, target, req).await
+    }
+
+    /// Send a response via the underlying connector, caching the response for retransmitted requests
+    async fn respond(&mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp) -> Result<(), E> {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert((target.clone(), req_id.clone()), resp.clone(), ctx.clone());
+
+        self.conn.respond(ctx, req_id, target, resp).await
+    }
+}
+
+// Stream implementation to allow polling for incoming requests, answering cached requests internally
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Stream for ResponseCache<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Clone + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + Send + 'static,
+    Conn: Connector<ReqId, Target, Req, Resp, E, Ctx>
+        + Stream<Item = Incoming<ReqId, Target, Req, Ctx>>
+        + Clone
+        + Unpin
+        + Send
+        + 'static,
+{
+    type Item = Incoming<ReqId, Target, Req, Ctx>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let s = self.get_mut();
+
+        loop {
+            // Complete any outstanding resend before polling for further requests
+            if let Some(f) = s.resend.as_mut() {
+                match f.poll_unpin(cx) {
+                    Poll::Ready(Err(e)) => warn!("Failed to resend cached response: {:?}", e),
+                    Poll::Ready(Ok(_)) => (),
+                    Poll::Pending => return Poll::Pending,
+                }
+                s.resend = None;
+            }
+
+            let (id, target, req, ctx) = match s.conn.poll_next_unpin(cx) {
+                Poll::Ready(Some(i)) => i,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let cached = {
+                let mut inner = s.inner.lock().unwrap();
+                inner.expire();
+
+                let cached = inner.window.get(&(target.clone(), id.clone())).cloned();
+                match cached.is_some() {
+                    true => inner.stats.hits += 1,
+                    false => inner.stats.misses += 1,
+                }
+
+                cached
+            };
+
+            match cached {
+                Some((resp, ctx)) => {
+                    debug!("Request id: '{:?}' from {:?}, answering from cache", id, target);
+
+                    let mut conn = s.conn.clone();
+                    s.resend = Some(async move { conn.respond(ctx, id, target, resp).await }.boxed());
+                }
+                None => return Poll::Ready(Some((id, target, req, ctx))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::LengthPrefix;
+    use crate::mock::{MockConnector, MockTransaction};
+    use crate::stream::{Error, StreamConnector};
+
+    type Conn = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, ()>;
+
+    #[test]
+    fn test_response_cache() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let handled = Arc::new(AtomicUsize::new(0));
+            let h = handled.clone();
+
+            let (tx, mut rx) = futures::channel::mpsc::unbounded();
+
+            task::spawn(async move {
+                let (s, _) = listener.accept().await.unwrap();
+                let (conn, driver): (Conn, _) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 0x11, ());
+                task::spawn(driver.run());
+
+                let mut conn: ResponseCache<_, _, _, _, Error, _, _> = ResponseCache::new(conn);
+                tx.unbounded_send(conn.clone()).unwrap();
+
+                while let Some((id, from, _req, _ctx)) = conn.next().await {
+                    let n = h.fetch_add(1, Ordering::SeqCst) as u8;
+                    conn.respond((), id, from, vec![n]).await.unwrap();
+                }
+            });
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (mut conn, driver): (Conn, _) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 0x22, ());
+            task::spawn(driver.run());
+
+            let cache = rx.next().await.unwrap();
+
+            // Retransmitted requests are answered with the cached response
+            assert_eq!(conn.request((), 1, 0x22, vec![]).await.unwrap(), vec![0]);
+            assert_eq!(conn.request((), 1, 0x22, vec![]).await.unwrap(), vec![0]);
+            assert_eq!(conn.request((), 2, 0x22, vec![]).await.unwrap(), vec![1]);
+
+            assert_eq!(handled.load(Ordering::SeqCst), 2);
+
+            let stats = cache.stats();
+            assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+        });
+    }
+
+    type Mock = MockConnector<u32, u8, u8, (), ()>;
+
+    #[test]
+    fn test_response_cache_eviction() {
+        let mut m = Mock::new();
+        m.expect((0..4).map(|i| MockTransaction::response(0x11, i, None)).collect::<Vec<_>>());
+
+        let (clock, ttl) = (VirtualClock::new(), Duration::from_secs(10));
+        let mut c = ResponseCache::<u16, u32, u8, u8, (), (), Mock>::new(m.clone())
+            .with_capacity(2)
+            .with_ttl(ttl)
+            .with_clock(clock.clone());
+
+        // Oldest entries are evicted to remain within capacity
+        for i in 0..3 {
+            block_on(c.respond((), i as u16, 0x11, i)).unwrap();
+        }
+        let stats = c.stats();
+        assert_eq!((stats.entries, stats.evicted, stats.expired), (2, 1, 0));
+
+        // Replaced entries are not double counted
+        block_on(c.respond((), 2, 0x11, 3)).unwrap();
+        let stats = c.stats();
+        assert_eq!((stats.entries, stats.evicted, stats.expired), (2, 1, 0));
+
+        // And remaining entries expire after the TTL
+        clock.advance(ttl - Duration::from_secs(1));
+        assert_eq!(c.stats().entries, 2);
+
+        clock.advance(Duration::from_secs(1));
+        let stats = c.stats();
+        assert_eq!((stats.entries, stats.evicted, stats.expired), (0, 1, 2));
+
+        m.finalise();
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use futures_timer::Delay;
+#[cfg(feature = "faults")]
+use rand::Rng;
+
+/// Clock provides the current time and timed sleeps, allowing simulated delays to run against
+/// either system time or a manually advanced virtual clock
+pub trait Clock: Send + Sync {
+    /// Fetch the current time
+    fn now(&self) -> Instant;
+
+    /// Sleep for the provided duration
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// SystemClock uses system time and timers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()> {
+        Delay::new(d).boxed()
+    }
+}
+
+struct Virtual {
+    now: Instant,
+    sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+}
+
+/// VirtualClock is a clock that only advances when instructed, waking sleepers whose deadlines have passed.
+/// This allows tests of timeout and retry logic to run deterministically without waiting on real time
+#[derive(Clone)]
+pub struct VirtualClock {
+    inner: Arc<Mutex<Virtual>>,
+}
+
+impl VirtualClock {
+    /// Create a new virtual clock starting at the current system time
+    pub fn new() -> Self {
+        VirtualClock {
+            inner: Arc::new(Mutex::new(Virtual {
+                now: Instant::now(),
+                sleepers: vec![],
+            })),
+        }
+    }
+
+    /// Advance the clock by the provided duration, waking any expired sleepers
+    pub fn advance(&self, d: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.now += d;
+
+        let now = inner.now;
+        let (expired, pending) = inner.sleepers.drain(..).partition(|(deadline, _)| *deadline <= now);
+        inner.sleepers = pending;
+
+        for (_, tx) in expired {
+            let _ = tx.send(());
+        }
+    }
+
+    /// Fetch the number of sleepers waiting on the clock
+    pub fn sleepers(&self) -> usize {
+        self.inner.lock().unwrap().sleepers.len()
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn sleep(&self, d: Duration) -> BoxFuture<'static, ()> {
+        let mut inner = self.inner.lock().unwrap();
+        if d == Duration::from_secs(0) {
+            return future::ready(()).boxed();
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let deadline = inner.now + d;
+        inner.sleepers.push((deadline, tx));
+
+        rx.map(|_| ()).boxed()
+    }
+}
+
+/// Jitter describes a random variation added to simulated delays
+#[cfg(feature = "faults")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Jitter {
+    /// No variation
+    #[default]
+    None,
+    /// Uniformly distributed between zero and the provided maximum
+    Uniform(Duration),
+    /// Exponentially distributed with the provided mean, bounded to ten times the mean
+    Exponential(Duration),
+}
+
+#[cfg(feature = "faults")]
+impl Jitter {
+    /// Sample an additional delay using the provided random number generator
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        match self {
+            Jitter::None => Duration::from_secs(0),
+            Jitter::Uniform(max) => max.mul_f64(rng.gen::<f64>()),
+            Jitter::Exponential(mean) => {
+                let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+                mean.mul_f64((-u.ln()).min(10.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    #[cfg(feature = "faults")]
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_virtual_clock() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+
+        block_on(async {
+            let mut a = clock.sleep(Duration::from_secs(1));
+            let mut b = clock.sleep(Duration::from_secs(3));
+            assert!(futures::poll!(&mut a).is_pending());
+            assert_eq!(clock.sleepers(), 2);
+
+            // Sleepers are woken only once the clock passes their deadline
+            clock.advance(Duration::from_secs(2));
+            assert!(futures::poll!(&mut a).is_ready());
+            assert!(futures::poll!(&mut b).is_pending());
+            assert_eq!(clock.sleepers(), 1);
+
+            clock.advance(Duration::from_secs(1));
+            b.await;
+        });
+
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+
+    #[cfg(feature = "faults")]
+    #[test]
+    fn test_jitter() {
+        let max = Duration::from_millis(100);
+        let (mut a, mut b) = (StdRng::seed_from_u64(7), StdRng::seed_from_u64(7));
+
+        for j in &[Jitter::Uniform(max), Jitter::Exponential(max)] {
+            let samples: Vec<_> = (0..100).map(|_| j.sample(&mut a)).collect();
+
+            // Samples are bounded and reproducible for a given seed
+            assert!(samples.iter().all(|d| *d <= max * 10));
+            assert!(samples.iter().any(|d| *d != samples[0]));
+            assert_eq!(samples, (0..100).map(|_| j.sample(&mut b)).collect::<Vec<_>>());
+        }
+
+        assert_eq!(Jitter::None.sample(&mut a), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,228 @@
+use std::convert::TryInto;
+use std::fmt::{self, Debug};
+
+use crate::muxed::Muxed;
+
+/// Kind identifies whether an encoded envelope carries a request or a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Request = 0x01,
+    Response = 0x02,
+}
+
+impl Kind {
+    /// Parse an envelope kind from its wire representation
+    pub fn from_u8(v: u8) -> Option<Kind> {
+        match v {
+            0x01 => Some(Kind::Request),
+            0x02 => Some(Kind::Response),
+            _ => None,
+        }
+    }
+}
+
+impl<Req, Resp> From<&Muxed<Req, Resp>> for Kind {
+    fn from(m: &Muxed<Req, Resp>) -> Kind {
+        match m {
+            Muxed::Request(_) => Kind::Request,
+            Muxed::Response(_) => Kind::Response,
+        }
+    }
+}
+
+/// Codec encodes and decodes Muxed messages with their request IDs to and from bytes.
+/// This provides a common serialisation boundary for transports carrying Mux messages
+pub trait Codec<ReqId, Req, Resp> {
+    type Error: Debug;
+
+    /// Encode a request ID and message into an envelope
+    fn encode(&self, id: &ReqId, msg: &Muxed<Req, Resp>) -> Result<Vec<u8>, Self::Error>;
+
+    /// Decode a request ID and message from an envelope
+    fn decode(&self, data: &[u8]) -> Result<(ReqId, Muxed<Req, Resp>), Self::Error>;
+}
+
+/// CodecError describes failures encoding or decoding the default envelope
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodecError {
+    /// Frame is shorter than the envelope header or declared payload
+    Truncated { expected: usize, actual: usize },
+    /// Frame has an unrecognised kind field
+    UnknownKind(u8),
+    /// Frame contains data beyond the declared payload
+    TrailingBytes(usize),
+    /// Payload is too long to be described by the length field
+    Oversized(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Truncated { expected, actual } => {
+                write!(f, "truncated frame (expected {} bytes, found {})", expected, actual)
+            }
+            CodecError::UnknownKind(k) => write!(f, "unknown frame kind: 0x{:02x}", k),
+            CodecError::TrailingBytes(n) => write!(f, "{} trailing bytes after payload", n),
+            CodecError::Oversized(n) => write!(f, "payload of {} bytes exceeds maximum length", n),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// EnvelopeId is implemented for request ID types supported by the default envelope,
+/// these are encoded as fixed width big-endian integers
+pub trait EnvelopeId: Sized {
+    /// Encoded length of the ID in bytes
+    const LEN: usize;
+
+    /// Append the encoded ID to the provided buffer
+    fn write(&self, buff: &mut Vec<u8>);
+
+    /// Read an ID from a buffer of exactly LEN bytes
+    fn read(buff: &[u8]) -> Self;
+}
+
+macro_rules! envelope_id {
+    ($($t:ty),*) => {
+        $(
+            impl EnvelopeId for $t {
+                const LEN: usize = std::mem::size_of::<$t>();
+
+                fn write(&self, buff: &mut Vec<u8>) {
+                    buff.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn read(buff: &[u8]) -> Self {
+                    <$t>::from_be_bytes(buff.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+envelope_id!(u8, u16, u32, u64, u128);
+
+/// EnvelopeCodec is the default Codec implementation, carrying opaque byte payloads.
+///
+/// Envelopes are laid out as follows, with all integers encoded big-endian:
+///
+/// ```text
+/// | kind (1 byte) | id (EnvelopeId::LEN bytes) | length (4 bytes) | payload (length bytes) |
+/// ```
+///
+/// Where kind is `0x01` for requests and `0x02` for responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EnvelopeCodec;
+
+impl EnvelopeCodec {
+    /// Create a new envelope codec
+    pub fn new() -> EnvelopeCodec {
+        EnvelopeCodec
+    }
+}
+
+impl<ReqId, Req, Resp> Codec<ReqId, Req, Resp> for EnvelopeCodec
+where
+    ReqId: EnvelopeId,
+    Req: AsRef<[u8]> + From<Vec<u8>>,
+    Resp: AsRef<[u8]> + From<Vec<u8>>,
+{
+    type Error = CodecError;
+
+    fn encode(&self, id: &ReqId, msg: &Muxed<Req, Resp>) -> Result<Vec<u8>, CodecError> {
+        let payload = match msg {
+            Muxed::Request(req) => req.as_ref(),
+            Muxed::Response(resp) => resp.as_ref(),
+        };
+
+        if payload.len() > u32::MAX as usize {
+            return Err(CodecError::Oversized(payload.len()));
+        }
+
+        let mut buff = Vec::with_capacity(1 + ReqId::LEN + 4 + payload.len());
+
+        buff.push(Kind::from(msg) as u8);
+        id.write(&mut buff);
+        buff.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buff.extend_from_slice(payload);
+
+        Ok(buff)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(ReqId, Muxed<Req, Resp>), CodecError> {
+        let header_len = 1 + ReqId::LEN + 4;
+        if data.len() < header_len {
+            return Err(CodecError::Truncated { expected: header_len, actual: data.len() });
+        }
+
+        let kind = Kind::from_u8(data[0]).ok_or(CodecError::UnknownKind(data[0]))?;
+        let id = ReqId::read(&data[1..1 + ReqId::LEN]);
+
+        let len = u32::from_be_bytes(data[1 + ReqId::LEN..header_len].try_into().unwrap()) as usize;
+        let expected = header_len + len;
+        if data.len() < expected {
+            return Err(CodecError::Truncated { expected, actual: data.len() });
+        }
+        if data.len() > expected {
+            return Err(CodecError::TrailingBytes(data.len() - expected));
+        }
+
+        let payload = data[header_len..].to_vec();
+
+        let msg = match kind {
+            Kind::Request => Muxed::Request(Req::from(payload)),
+            Kind::Response => Muxed::Response(Resp::from(payload)),
+        };
+
+        Ok((id, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Msg = Muxed<Vec<u8>, Vec<u8>>;
+
+    #[test]
+    fn test_envelope_layout() {
+        let c = EnvelopeCodec::new();
+
+        let req: Msg = Muxed::Request(vec![0xaa, 0xbb]);
+        let encoded = c.encode(&0x0102u16, &req).unwrap();
+        assert_eq!(encoded, vec![0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x02, 0xaa, 0xbb]);
+
+        let resp: Msg = Muxed::Response(vec![0xcc]);
+        let encoded = c.encode(&0x03u8, &resp).unwrap();
+        assert_eq!(encoded, vec![0x02, 0x03, 0x00, 0x00, 0x00, 0x01, 0xcc]);
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let c = EnvelopeCodec::new();
+
+        for m in &[Muxed::Request(vec![1, 2, 3]), Muxed::Response(vec![]) as Msg] {
+            let encoded = c.encode(&0xdeadbeefu32, m).unwrap();
+            let decoded: (u32, Msg) = c.decode(&encoded).unwrap();
+            assert_eq!(decoded, (0xdeadbeef, m.clone()));
+        }
+    }
+
+    #[test]
+    fn test_envelope_errors() {
+        let c = EnvelopeCodec::new();
+
+        let r: Result<(u16, Msg), _> = c.decode(&[0x01, 0x00]);
+        assert_eq!(r, Err(CodecError::Truncated { expected: 7, actual: 2 }));
+
+        let r: Result<(u16, Msg), _> = c.decode(&[0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0xaa]);
+        assert_eq!(r, Err(CodecError::Truncated { expected: 9, actual: 8 }));
+
+        let r: Result<(u16, Msg), _> = c.decode(&[0x07, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(r, Err(CodecError::UnknownKind(0x07)));
+
+        let r: Result<(u16, Msg), _> = c.decode(&[0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xff]);
+        assert_eq!(r, Err(CodecError::TrailingBytes(1)));
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// DedupPolicy configures the handling of duplicate incoming requests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DedupPolicy {
+    /// Drop duplicate requests
+    Drop,
+    /// Answer duplicate requests with the cached response where available, otherwise drop them
+    Respond,
+}
+
+/// Dedup configures a window of recently seen (Target, ReqId) pairs used to filter duplicated or
+/// replayed incoming requests, bounded by both entry age and the number of entries
+#[derive(Clone)]
+pub struct Dedup {
+    policy: DedupPolicy,
+    ttl: Duration,
+    capacity: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for Dedup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dedup")
+            .field("policy", &self.policy)
+            .field("ttl", &self.ttl)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl Dedup {
+    /// Create a new dedup window with the provided policy.
+    /// By default entries are held for 30 seconds, with a maximum of 1024 entries
+    pub fn new(policy: DedupPolicy) -> Self {
+        Dedup {
+            policy,
+            ttl: Duration::from_secs(30),
+            capacity: 1024,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set the duration for which requests are remembered
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the maximum number of requests remembered
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Set the clock used to age requests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+/// Verdict is the result of checking an incoming request against a dedup window
+pub(crate) enum Verdict<Resp, Ctx> {
+    /// Request has not been seen
+    Fresh,
+    /// Request is a duplicate to be dropped
+    Drop,
+    /// Request is a duplicate to be answered with the cached response
+    Respond(Resp, Ctx),
+}
+
+/// Filter is the type erased interface to a dedup window, allowing the window to be held by a Mux
+/// without additional bounds on its type parameters
+pub(crate) trait Filter<ReqId, Target, Resp, Ctx>: Send {
+    /// Check an incoming request, recording it if not seen
+    fn check(&mut self, id: &ReqId, target: &Target) -> Verdict<Resp, Ctx>;
+
+    /// Record the response sent for a request
+    fn record(&mut self, id: &ReqId, target: &Target, resp: &Resp, ctx: &Ctx);
+}
+
+/// Window holds recently inserted entries, bounded by both entry age and the number of entries.
+/// Each key holds a single position in the eviction order, with replaced entries moved to the back
+pub(crate) struct Window<K, V> {
+    ttl: Duration,
+    capacity: usize,
+
+    entries: HashMap<K, (Instant, V)>,
+    order: VecDeque<K>,
+}
+
+impl<K, V> Window<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    pub(crate) fn new(ttl: Duration, capacity: usize) -> Self {
+        Window {
+            ttl,
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(_, v)| v)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entries.get_mut(key).map(|(_, v)| v)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Insert or replace an entry, returning the number of entries (expired, evicted) to remain
+    /// within the window bounds
+    pub(crate) fn insert(&mut self, key: K, value: V, now: Instant) -> (usize, usize) {
+        if self.entries.insert(key.clone(), (now, value)).is_some() {
+            self.order.retain(|k| k != &key);
+        }
+        self.order.push_back(key);
+
+        self.expire(now)
+    }
+
+    /// Evict entries exceeding the age then count bounds, oldest first, returning the number of
+    /// entries (expired, evicted)
+    pub(crate) fn expire(&mut self, now: Instant) -> (usize, usize) {
+        let (mut expired, mut evicted) = (0, 0);
+
+        while let Some(key) = self.order.front() {
+            match self.entries.get(key) {
+                Some((inserted, _)) if now.duration_since(*inserted) >= self.ttl => expired += 1,
+                Some(_) if self.order.len() > self.capacity => evicted += 1,
+                Some(_) => break,
+                None => (),
+            }
+
+            if let Some(key) = self.order.pop_front() {
+                self.entries.remove(&key);
+            }
+        }
+
+        (expired, evicted)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Seen filters incoming requests against a window of recently seen requests and their responses
+pub(crate) struct Seen<ReqId, Target, Resp, Ctx> {
+    policy: DedupPolicy,
+    window: Window<(Target, ReqId), Option<(Resp, Ctx)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<ReqId, Target, Resp, Ctx> Seen<ReqId, Target, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Clone,
+    Target: Eq + Hash + Clone,
+{
+    pub(crate) fn new(config: Dedup) -> Self {
+        Seen {
+            policy: config.policy,
+            window: Window::new(config.ttl, config.capacity),
+            clock: config.clock,
+        }
+    }
+}
+
+impl<ReqId, Target, Resp, Ctx> Filter<ReqId, Target, Resp, Ctx> for Seen<ReqId, Target, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Clone + Send,
+    Target: Eq + Hash + Clone + Send,
+    Resp: Clone + Send,
+    Ctx: Clone + Send,
+{
+    fn check(&mut self, id: &ReqId, target: &Target) -> Verdict<Resp, Ctx> {
+        let now = self.clock.now();
+        self.window.expire(now);
+
+        let key = (target.clone(), id.clone());
+
+        let resp = match self.window.get(&key) {
+            Some(resp) => resp.clone(),
+            None => {
+                self.window.insert(key, None, now);
+                return Verdict::Fresh;
+            }
+        };
+
+        match (self.policy, resp) {
+            (DedupPolicy::Respond, Some((resp, ctx))) => Verdict::Respond(resp, ctx),
+            _ => Verdict::Drop,
+        }
+    }
+
+    fn record(&mut self, id: &ReqId, target: &Target, resp: &Resp, ctx: &Ctx) {
+        if self.policy != DedupPolicy::Respond {
+            return;
+        }
+
+        if let Some(e) = self.window.get_mut(&(target.clone(), id.clone())) {
+            *e = Some((resp.clone(), ctx.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::prelude::*;
+
+    use crate::clock::VirtualClock;
+    use crate::connector::Connector;
+    use crate::mux::Mux;
+    use crate::muxed::Muxed;
+
+    use super::*;
+
+    type TestMux = Mux<u16, u32, u8, u8, (), ()>;
+
+    #[test]
+    fn test_dedup_drop() {
+        let mut mux = TestMux::new(|_| ()).with_dedup(Dedup::new(DedupPolicy::Drop));
+
+        assert_eq!(mux.handle(1, 10, Muxed::Request(1)), Ok(Some((10, 1))));
+
+        // Duplicates from the same target are dropped
+        assert_eq!(mux.handle(1, 10, Muxed::Request(1)), Ok(None));
+
+        // While the same ID from other targets is not
+        assert_eq!(mux.handle(1, 20, Muxed::Request(1)), Ok(Some((20, 1))));
+        assert_eq!(mux.handle(2, 10, Muxed::Request(2)), Ok(Some((10, 2))));
+    }
+
+    #[test]
+    fn test_dedup_respond() {
+        let mut mux = TestMux::new(|_| ()).with_dedup(Dedup::new(DedupPolicy::Respond));
+
+        assert_eq!(mux.handle(1, 10, Muxed::Request(1)), Ok(Some((10, 1))));
+
+        // Duplicates received before a response are dropped
+        assert_eq!(mux.handle(1, 10, Muxed::Request(1)), Ok(None));
+
+        let mut m = mux.clone();
+        let (r, sent) = block_on(future::join(m.respond((), 1, 10, 7), mux.next()));
+        assert_eq!(r, Ok(()));
+        assert_eq!(sent, Some((1, 10, Muxed::Response(7), ())));
+
+        // Later duplicates are answered with the cached response
+        assert_eq!(mux.handle(1, 10, Muxed::Request(1)), Ok(None));
+        assert_eq!(block_on(mux.next()), Some((1, 10, Muxed::Response(7), ())));
+    }
+
+    #[test]
+    fn test_dedup_bounds() {
+        // Entries are evicted beyond the capacity of the window
+        let mut mux = TestMux::new(|_| ()).with_dedup(Dedup::new(DedupPolicy::Drop).with_capacity(2));
+
+        for id in 1..=3 {
+            assert_eq!(mux.handle(id, 10, Muxed::Request(0)), Ok(Some((10, 0))));
+        }
+        assert_eq!(mux.handle(3, 10, Muxed::Request(0)), Ok(None));
+        assert_eq!(mux.handle(1, 10, Muxed::Request(0)), Ok(Some((10, 0))));
+
+        // And once they exceed the TTL
+        let (clock, ttl) = (VirtualClock::new(), Duration::from_secs(10));
+        let dedup = Dedup::new(DedupPolicy::Drop).with_ttl(ttl).with_clock(clock.clone());
+        let mut mux = TestMux::new(|_| ()).with_dedup(dedup);
+
+        assert_eq!(mux.handle(1, 10, Muxed::Request(0)), Ok(Some((10, 0))));
+        clock.advance(ttl - Duration::from_secs(1));
+        assert_eq!(mux.handle(1, 10, Muxed::Request(0)), Ok(None));
+
+        clock.advance(ttl);
+        assert_eq!(mux.handle(1, 10, Muxed::Request(0)), Ok(Some((10, 0))));
+    }
+
+    #[test]
+    fn test_window() {
+        let clock = VirtualClock::new();
+        let now = clock.now();
+        let mut w = Window::new(Duration::from_secs(10), 2);
+
+        // Replacing an entry keeps a single position in the eviction order
+        for i in 0..4 {
+            assert_eq!(w.insert(1, i, now), (0, 0));
+        }
+        assert_eq!((w.len(), w.order.len(), w.get(&1)), (1, 1, Some(&3)));
+
+        // Replaced entries are moved to the back of the eviction order
+        assert_eq!(w.insert(2, 0, now), (0, 0));
+        assert_eq!(w.insert(1, 4, now), (0, 0));
+        assert_eq!(w.insert(3, 0, now), (0, 1));
+        assert_eq!((w.get(&1), w.get(&2), w.get(&3)), (Some(&4), None, Some(&0)));
+
+        // And entries expire after the TTL
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(w.expire(clock.now()), (2, 0));
+        assert_eq!((w.len(), w.order.len()), (0, 0));
+    }
+}
@@ -0,0 +1,338 @@
+use std::convert::TryInto;
+use std::fmt;
+
+/// Default maximum frame length in bytes
+pub const DEFAULT_MAX_LEN: usize = 1024 * 1024;
+
+/// Framing splits a byte stream into discrete frames
+pub trait Framing {
+    /// Append an encoded frame to the provided buffer
+    fn encode(&self, frame: &[u8], buff: &mut Vec<u8>) -> Result<(), FrameError>;
+
+    /// Attempt to decode a frame from the start of the provided buffer,
+    /// removing consumed bytes and returning None if a complete frame is not yet available
+    fn decode(&self, buff: &mut Vec<u8>) -> Result<Option<Vec<u8>>, FrameError>;
+}
+
+/// FrameError describes failures encoding or decoding frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    /// Frame exceeds the maximum configured length
+    TooLong(usize),
+    /// Frame contains the delimiter and cannot be encoded
+    Delimiter,
+    /// Frame encoding is invalid
+    Invalid,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::TooLong(n) => write!(f, "frame of {} bytes exceeds maximum length", n),
+            FrameError::Delimiter => write!(f, "frame contains delimiter"),
+            FrameError::Invalid => write!(f, "invalid frame encoding"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// LengthPrefix frames messages with a 4 byte big-endian length header
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthPrefix {
+    max_len: usize,
+}
+
+impl LengthPrefix {
+    /// Create a new length prefix framing with the default maximum frame length
+    pub fn new() -> Self {
+        LengthPrefix { max_len: DEFAULT_MAX_LEN }
+    }
+
+    /// Set the maximum frame length
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len.min(u32::MAX as usize);
+        self
+    }
+}
+
+impl Default for LengthPrefix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framing for LengthPrefix {
+    fn encode(&self, frame: &[u8], buff: &mut Vec<u8>) -> Result<(), FrameError> {
+        if frame.len() > self.max_len {
+            return Err(FrameError::TooLong(frame.len()));
+        }
+
+        buff.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        buff.extend_from_slice(frame);
+
+        Ok(())
+    }
+
+    fn decode(&self, buff: &mut Vec<u8>) -> Result<Option<Vec<u8>>, FrameError> {
+        if buff.len() < 4 {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes(buff[..4].try_into().unwrap()) as usize;
+        if len > self.max_len {
+            return Err(FrameError::TooLong(len));
+        }
+        if buff.len() < 4 + len {
+            return Ok(None);
+        }
+
+        let frame = buff[4..4 + len].to_vec();
+        buff.drain(..4 + len);
+
+        Ok(Some(frame))
+    }
+}
+
+/// Cobs frames messages using Consistent Overhead Byte Stuffing, with a zero byte delimiter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cobs {
+    max_len: usize,
+}
+
+impl Cobs {
+    /// Create a new COBS framing with the default maximum frame length
+    pub fn new() -> Self {
+        Cobs { max_len: DEFAULT_MAX_LEN }
+    }
+
+    /// Set the maximum frame length
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl Default for Cobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framing for Cobs {
+    fn encode(&self, frame: &[u8], buff: &mut Vec<u8>) -> Result<(), FrameError> {
+        if frame.len() > self.max_len {
+            return Err(FrameError::TooLong(frame.len()));
+        }
+
+        let mut code_index = buff.len();
+        let mut code = 1u8;
+        buff.push(0);
+
+        for b in frame {
+            if *b == 0 {
+                buff[code_index] = code;
+                code_index = buff.len();
+                code = 1;
+                buff.push(0);
+                continue;
+            }
+
+            buff.push(*b);
+            code += 1;
+
+            if code == 0xff {
+                buff[code_index] = code;
+                code_index = buff.len();
+                code = 1;
+                buff.push(0);
+            }
+        }
+
+        buff[code_index] = code;
+        buff.push(0);
+
+        Ok(())
+    }
+
+    fn decode(&self, buff: &mut Vec<u8>) -> Result<Option<Vec<u8>>, FrameError> {
+        loop {
+            let end = match buff.iter().position(|b| *b == 0) {
+                Some(end) => end,
+                None if buff.len() > self.max_len + self.max_len / 254 + 1 => {
+                    return Err(FrameError::TooLong(buff.len()))
+                }
+                None => return Ok(None),
+            };
+
+            // Skip empty frames between delimiters
+            if end == 0 {
+                buff.remove(0);
+                continue;
+            }
+
+            let mut frame = Vec::with_capacity(end);
+            let mut i = 0;
+
+            while i < end {
+                let code = buff[i] as usize;
+                if i + code > end {
+                    buff.drain(..=end);
+                    return Err(FrameError::Invalid);
+                }
+
+                frame.extend_from_slice(&buff[i + 1..i + code]);
+                i += code;
+
+                if code != 0xff && i < end {
+                    frame.push(0);
+                }
+            }
+
+            buff.drain(..=end);
+
+            if frame.len() > self.max_len {
+                return Err(FrameError::TooLong(frame.len()));
+            }
+
+            return Ok(Some(frame));
+        }
+    }
+}
+
+/// Newline frames messages with a trailing newline, for use with text based codecs.
+/// Trailing carriage returns are removed from decoded frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Newline {
+    max_len: usize,
+}
+
+impl Newline {
+    /// Create a new newline delimited framing with the default maximum frame length
+    pub fn new() -> Self {
+        Newline { max_len: DEFAULT_MAX_LEN }
+    }
+
+    /// Set the maximum frame length
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl Default for Newline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framing for Newline {
+    fn encode(&self, frame: &[u8], buff: &mut Vec<u8>) -> Result<(), FrameError> {
+        if frame.len() > self.max_len {
+            return Err(FrameError::TooLong(frame.len()));
+        }
+        if frame.contains(&b'\n') {
+            return Err(FrameError::Delimiter);
+        }
+
+        buff.extend_from_slice(frame);
+        buff.push(b'\n');
+
+        Ok(())
+    }
+
+    fn decode(&self, buff: &mut Vec<u8>) -> Result<Option<Vec<u8>>, FrameError> {
+        let end = match buff.iter().position(|b| *b == b'\n') {
+            Some(end) => end,
+            None if buff.len() > self.max_len + 1 => return Err(FrameError::TooLong(buff.len())),
+            None => return Ok(None),
+        };
+
+        let mut frame: Vec<u8> = buff.drain(..=end).take(end).collect();
+        if frame.last() == Some(&b'\r') {
+            frame.pop();
+        }
+
+        if frame.len() > self.max_len {
+            return Err(FrameError::TooLong(frame.len()));
+        }
+
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<F: Framing>(f: F, frames: &[&[u8]]) {
+        let mut buff = vec![];
+        for frame in frames {
+            f.encode(frame, &mut buff).unwrap();
+        }
+
+        // Decode byte by byte to exercise partial frames
+        let mut rx = vec![];
+        let mut decoded = vec![];
+        for b in buff {
+            rx.push(b);
+            while let Some(frame) = f.decode(&mut rx).unwrap() {
+                decoded.push(frame);
+            }
+        }
+
+        assert_eq!(decoded, frames);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_length_prefix() {
+        let f = LengthPrefix::new();
+
+        let mut buff = vec![];
+        f.encode(&[0xaa, 0xbb], &mut buff).unwrap();
+        assert_eq!(buff, vec![0x00, 0x00, 0x00, 0x02, 0xaa, 0xbb]);
+
+        round_trip(f.clone(), &[&[1, 2, 3], &[], &[0; 300]]);
+
+        let f = f.with_max_len(2);
+        assert_eq!(f.encode(&[1, 2, 3], &mut vec![]), Err(FrameError::TooLong(3)));
+        assert_eq!(f.decode(&mut vec![0, 0, 0, 3]), Err(FrameError::TooLong(3)));
+    }
+
+    #[test]
+    fn test_cobs() {
+        let f = Cobs::new();
+
+        let vectors: &[(&[u8], &[u8])] = &[
+            (&[0x00], &[0x01, 0x01, 0x00]),
+            (&[0x00, 0x00], &[0x01, 0x01, 0x01, 0x00]),
+            (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33, 0x00]),
+            (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01, 0x00]),
+        ];
+        for (frame, encoded) in vectors {
+            let mut buff = vec![];
+            f.encode(frame, &mut buff).unwrap();
+            assert_eq!(&buff, encoded);
+        }
+
+        let long: Vec<u8> = (1..=255).collect();
+        let long_zero: Vec<u8> = (0..=255).collect();
+        round_trip(f.clone(), &[&[1, 0, 2], &long, &long_zero, &[0, 0]]);
+
+        assert_eq!(f.decode(&mut vec![0x05, 0x11, 0x00]), Err(FrameError::Invalid));
+    }
+
+    #[test]
+    fn test_newline() {
+        let f = Newline::new();
+
+        round_trip(f.clone(), &[b"{\"a\": 1}", b"hello"]);
+
+        let mut buff = b"abc\r\n".to_vec();
+        assert_eq!(f.decode(&mut buff), Ok(Some(b"abc".to_vec())));
+
+        assert_eq!(f.encode(b"a\nb", &mut vec![]), Err(FrameError::Delimiter));
+        assert_eq!(f.with_max_len(2).decode(&mut b"abcd".to_vec()), Err(FrameError::TooLong(4)));
+    }
+}
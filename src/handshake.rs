@@ -0,0 +1,347 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use async_trait::async_trait;
+use futures::io::{AsyncRead, AsyncWrite};
+
+use crate::codec::Codec;
+use crate::framing::Framing;
+use crate::stream::{Error, StreamConnector, StreamDriver};
+
+/// Role of the local end of a connection in a handshake
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    /// The end that established the connection
+    Initiator,
+    /// The end that accepted the connection
+    Responder,
+}
+
+impl Role {
+    /// Fetch the role of the remote end of the connection
+    pub fn peer(&self) -> Role {
+        match self {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        }
+    }
+}
+
+/// Handshake is run over a newly established stream before it carries any Muxed traffic,
+/// for example to authenticate the remote peer or to establish session keys.
+/// The output of a successful handshake is passed as the Ctx with each incoming request.
+#[async_trait]
+pub trait Handshake<S> {
+    type Output;
+
+    /// Run the handshake over the provided stream, failing with Error::Handshake where the peer is rejected
+    async fn handshake(&self, stream: &mut S, role: Role) -> Result<Self::Output, Error>;
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> StreamConnector<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Create a new connector over the provided stream once the provided handshake has completed,
+    /// passing the handshake output as the Ctx with each incoming request.
+    #[allow(clippy::type_complexity)]
+    pub async fn handshake<S, H, C, F>(
+        mut stream: S, handshake: &H, role: Role, codec: C, framing: F, target: Target,
+    ) -> Result<(Self, StreamDriver<ReqId, Target, Req, Resp, Ctx, S, C, F>), Error>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+        H: Handshake<S, Output = Ctx>,
+        C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+        F: Framing + Clone + Send + 'static,
+    {
+        let ctx = handshake.handshake(&mut stream, role).await?;
+
+        debug!("Handshake with {:?} complete ({:?})", target, ctx);
+
+        Ok(Self::new(stream, codec, framing, target, ctx))
+    }
+}
+
+#[cfg(feature = "hmac")]
+pub use self::hmac_challenge::HmacChallenge;
+
+#[cfg(feature = "hmac")]
+mod hmac_challenge {
+    use std::convert::TryFrom;
+
+    use async_trait::async_trait;
+    use futures::io::{AsyncRead, AsyncWrite};
+    use futures::prelude::*;
+    use hmac::{Hmac, Mac};
+    use rand::RngCore;
+    use sha2::Sha256;
+
+    use super::{Handshake, Role};
+    use crate::stream::Error;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    const NONCE_LEN: usize = 32;
+    const MAC_LEN: usize = 32;
+
+    /// HmacChallenge is a mutual challenge-response handshake using a shared secret.
+    /// Each end sends its ID and a random nonce, then proves knowledge of the secret with an HMAC-SHA256
+    /// over both nonces, its role and its ID. The output is the authenticated ID of the remote peer.
+    #[derive(Clone)]
+    pub struct HmacChallenge {
+        id: Vec<u8>,
+        secret: Vec<u8>,
+    }
+
+    impl HmacChallenge {
+        /// Create a new handshake with the local ID and shared secret
+        pub fn new(id: impl Into<Vec<u8>>, secret: impl Into<Vec<u8>>) -> Self {
+            HmacChallenge {
+                id: id.into(),
+                secret: secret.into(),
+            }
+        }
+
+        fn mac(&self, role: Role, initiator: &[u8], responder: &[u8], id: &[u8]) -> HmacSha256 {
+            let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+
+            mac.update(match role {
+                Role::Initiator => b"rr-mux initiator",
+                Role::Responder => b"rr-mux responder",
+            });
+            mac.update(initiator);
+            mac.update(responder);
+            mac.update(id);
+
+            mac
+        }
+    }
+
+    #[async_trait]
+    impl<S> Handshake<S> for HmacChallenge
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        type Output = Vec<u8>;
+
+        async fn handshake(&self, stream: &mut S, role: Role) -> Result<Vec<u8>, Error> {
+            let mut nonce = [0u8; NONCE_LEN];
+            rand::thread_rng().fill_bytes(&mut nonce);
+
+            // Exchange IDs and nonces
+            let id_len = u16::try_from(self.id.len()).map_err(|_| Error::Handshake("local ID too long".into()))?;
+
+            let mut hello = Vec::with_capacity(2 + self.id.len() + NONCE_LEN);
+            hello.extend_from_slice(&id_len.to_be_bytes());
+            hello.extend_from_slice(&self.id);
+            hello.extend_from_slice(&nonce);
+            stream.write_all(&hello).await?;
+            stream.flush().await?;
+
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await?;
+            let mut peer_id = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut peer_id).await?;
+            let mut peer_nonce = [0u8; NONCE_LEN];
+            stream.read_exact(&mut peer_nonce).await?;
+
+            let (initiator, responder) = match role {
+                Role::Initiator => (&nonce, &peer_nonce),
+                Role::Responder => (&peer_nonce, &nonce),
+            };
+
+            // Exchange and verify proofs
+            let proof = self.mac(role, initiator, responder, &self.id).finalize().into_bytes();
+            stream.write_all(&proof).await?;
+            stream.flush().await?;
+
+            let mut peer_proof = [0u8; MAC_LEN];
+            stream.read_exact(&mut peer_proof).await?;
+
+            self.mac(role.peer(), initiator, responder, &peer_id)
+                .verify_slice(&peer_proof)
+                .map_err(|_| Error::Handshake("peer authentication failed".into()))?;
+
+            Ok(peer_id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures::prelude::*;
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::connector::Connector;
+    use crate::framing::LengthPrefix;
+
+    /// Hello exchanges single byte IDs, rejecting peers with an ID of zero
+    struct Hello(u8);
+
+    #[async_trait]
+    impl<S> Handshake<S> for Hello
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        type Output = u8;
+
+        async fn handshake(&self, stream: &mut S, _role: Role) -> Result<u8, Error> {
+            stream.write_all(&[self.0]).await?;
+
+            let mut peer = [0u8; 1];
+            stream.read_exact(&mut peer).await?;
+
+            match peer[0] {
+                0 => Err(Error::Handshake("invalid peer".into())),
+                id => Ok(id),
+            }
+        }
+    }
+
+    /// HelloVec wraps Hello to produce a byte vector output
+    struct HelloVec(u8);
+
+    #[async_trait]
+    impl Handshake<TcpStream> for HelloVec {
+        type Output = Vec<u8>;
+
+        async fn handshake(&self, stream: &mut TcpStream, role: Role) -> Result<Vec<u8>, Error> {
+            Hello(self.0).handshake(stream, role).await.map(|id| vec![id])
+        }
+    }
+
+    type Conn<Ctx> = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, Ctx>;
+
+    // Start a server completing the provided handshake and responding to requests with the handshake output,
+    // returning the server address and a channel of handshake results
+    async fn server<H>(h: H) -> (std::net::SocketAddr, futures::channel::mpsc::UnboundedReceiver<Result<(), Error>>)
+    where
+        H: Handshake<TcpStream> + Send + Sync + 'static,
+        H::Output: Debug + Clone + Send + Into<Vec<u8>> + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+
+        task::spawn(async move {
+            let (s, _) = listener.accept().await.unwrap();
+
+            let (mut conn, driver): (Conn<H::Output>, _) =
+                match StreamConnector::handshake(s, &h, Role::Responder, EnvelopeCodec, LengthPrefix::new(), 0x11)
+                    .await
+                {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tx.unbounded_send(Err(e)).unwrap();
+                        return;
+                    }
+                };
+            tx.unbounded_send(Ok(())).unwrap();
+            task::spawn(driver.run());
+
+            while let Some((id, from, _req, ctx)) = conn.next().await {
+                conn.respond(ctx.clone(), id, from, ctx.into()).await.unwrap();
+            }
+        });
+
+        (addr, rx)
+    }
+
+    #[test]
+    fn test_handshake_ctx() {
+        task::block_on(async {
+            let (addr, mut results) = server(HelloVec(0x11)).await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (mut conn, driver): (Conn<u8>, _) =
+                StreamConnector::handshake(s, &Hello(0x22), Role::Initiator, EnvelopeCodec, LengthPrefix::new(), 0x22)
+                    .await
+                    .unwrap();
+            task::spawn(driver.run());
+
+            assert!(results.next().await.unwrap().is_ok());
+
+            // The handshake output is passed to the server handler as the request Ctx
+            let resp = conn.request(0x11, 1, 0x22, vec![]).await.unwrap();
+            assert_eq!(resp, vec![0x22]);
+        });
+    }
+
+    #[test]
+    fn test_handshake_rejected() {
+        task::block_on(async {
+            let (addr, mut results) = server(HelloVec(0x11)).await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let _ = StreamConnector::<u16, u32, Vec<u8>, Vec<u8>, u8>::handshake(
+                s,
+                &Hello(0),
+                Role::Initiator,
+                EnvelopeCodec,
+                LengthPrefix::new(),
+                0x22,
+            )
+            .await;
+
+            // Connections failing the handshake are never handed to the server
+            assert!(matches!(results.next().await.unwrap(), Err(Error::Handshake(_))));
+        });
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_challenge() {
+        task::block_on(async {
+            let (addr, mut results) = server(HmacChallenge::new("server", "secret")).await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (mut conn, driver): (Conn<Vec<u8>>, _) = StreamConnector::handshake(
+                s,
+                &HmacChallenge::new("client", "secret"),
+                Role::Initiator,
+                EnvelopeCodec,
+                LengthPrefix::new(),
+                0x22,
+            )
+            .await
+            .unwrap();
+            task::spawn(driver.run());
+
+            assert!(results.next().await.unwrap().is_ok());
+
+            // Authenticated peer IDs are available on both ends
+            let resp = conn.request(b"server".to_vec(), 1, 0x22, vec![]).await.unwrap();
+            assert_eq!(resp, b"client".to_vec());
+        });
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_challenge_wrong_secret() {
+        task::block_on(async {
+            let (addr, mut results) = server(HmacChallenge::new("server", "secret")).await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let res: Result<(Conn<Vec<u8>>, _), _> = StreamConnector::handshake(
+                s,
+                &HmacChallenge::new("client", "guess"),
+                Role::Initiator,
+                EnvelopeCodec,
+                LengthPrefix::new(),
+                0x22,
+            )
+            .await;
+
+            // Both ends reject the handshake
+            assert!(matches!(res, Err(Error::Handshake(_))));
+            assert!(matches!(results.next().await.unwrap(), Err(Error::Handshake(_))));
+        });
+    }
+}
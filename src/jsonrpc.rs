@@ -0,0 +1,419 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::codec::Codec;
+use crate::muxed::Muxed;
+
+/// JSON-RPC protocol version string
+pub const VERSION: &str = "2.0";
+
+/// Id is a JSON-RPC request identifier.
+/// Requests without an ID (None) are notifications and expect no response, while Null is an explicit
+/// `null` ID as sent in error responses to requests whose ID could not be determined
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+    Null,
+}
+
+impl From<i64> for Id {
+    fn from(v: i64) -> Self {
+        Id::Number(v)
+    }
+}
+
+impl From<&str> for Id {
+    fn from(v: &str) -> Self {
+        Id::String(v.to_string())
+    }
+}
+
+/// RpcError is a JSON-RPC error object, returned in place of a result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    /// Create a new error object
+    pub fn new(code: i64, message: &str) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    /// Attach additional data to an error object
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+/// Message is a JSON-RPC request or response, where responses carry either a result or an error object
+pub type Message<Req, Resp> = Muxed<Req, Result<Resp, RpcError>>;
+
+/// Error describes failures encoding or decoding JSON-RPC messages
+#[derive(Debug)]
+pub enum Error {
+    /// Message is not valid JSON or does not match the expected types
+    Json(serde_json::Error),
+    /// Message is valid JSON but not a valid JSON-RPC object
+    Invalid(&'static str),
+}
+
+impl Error {
+    /// Convert a decode error to a JSON-RPC error object for returning to the peer
+    pub fn to_rpc_error(&self) -> RpcError {
+        match self {
+            Error::Json(e) if e.is_syntax() || e.is_eof() => {
+                RpcError::new(RpcError::PARSE_ERROR, "Parse error")
+            }
+            _ => RpcError::new(RpcError::INVALID_REQUEST, "Invalid Request"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::Invalid(reason) => write!(f, "invalid json-rpc message: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// JsonRpcCodec maps Muxed messages to and from JSON-RPC 2.0 objects.
+///
+/// Requests are encoded as `{"jsonrpc": "2.0", "method": .., "params": .., "id": ..}`,
+/// so Req must serialise to an object containing `method` and (optionally) `params` fields,
+/// for example an enum using `#[serde(tag = "method", content = "params")]`.
+/// Responses are `Result<Resp, RpcError>` and are encoded as `{"jsonrpc": "2.0", "result": .., "id": ..}`
+/// or `{"jsonrpc": "2.0", "error": {..}, "id": ..}`.
+///
+/// Notifications (requests with a None ID) never receive a response, so must be sent with `Mux::notify`
+/// rather than `Mux::request`, which would leave the request pending.
+pub struct JsonRpcCodec<Req, Resp> {
+    _req: PhantomData<Req>,
+    _resp: PhantomData<Resp>,
+}
+
+impl<Req, Resp> JsonRpcCodec<Req, Resp>
+where
+    Req: Serialize + DeserializeOwned,
+    Resp: Serialize + DeserializeOwned,
+{
+    /// Create a new JSON-RPC codec
+    pub fn new() -> Self {
+        JsonRpcCodec {
+            _req: PhantomData,
+            _resp: PhantomData,
+        }
+    }
+
+    /// Encode a message to a JSON-RPC object
+    pub fn encode_value(
+        &self, id: &Option<Id>, msg: &Message<Req, Resp>,
+    ) -> Result<Value, Error> {
+        let mut o = match msg {
+            Muxed::Request(req) => match serde_json::to_value(req)? {
+                Value::Object(o) if o.contains_key("method") => o,
+                _ => return Err(Error::Invalid("request must serialise to an object with a method")),
+            },
+            Muxed::Response(Ok(resp)) => {
+                let mut o = Map::new();
+                o.insert("result".to_string(), serde_json::to_value(resp)?);
+                o
+            }
+            Muxed::Response(Err(err)) => {
+                let mut o = Map::new();
+                o.insert("error".to_string(), serde_json::to_value(err)?);
+                o
+            }
+        };
+
+        o.insert("jsonrpc".to_string(), Value::from(VERSION));
+
+        // Notifications omit the ID, responses must include it
+        match (id, msg) {
+            (Some(id), _) => {
+                o.insert("id".to_string(), serde_json::to_value(id)?);
+            }
+            (None, Muxed::Response(_)) => {
+                return Err(Error::Invalid("responses must have an id"));
+            }
+            (None, Muxed::Request(_)) => (),
+        }
+
+        Ok(Value::Object(o))
+    }
+
+    /// Decode a message from a JSON-RPC object
+    pub fn decode_value(
+        &self, v: Value,
+    ) -> Result<(Option<Id>, Message<Req, Resp>), Error> {
+        let mut o = match v {
+            Value::Object(o) => o,
+            _ => return Err(Error::Invalid("message must be an object")),
+        };
+
+        if o.remove("jsonrpc") != Some(Value::from(VERSION)) {
+            return Err(Error::Invalid("missing or unsupported jsonrpc version"));
+        }
+
+        let id = match o.remove("id") {
+            None => None,
+            Some(id) => Some(serde_json::from_value(id)?),
+        };
+
+        if o.contains_key("result") && o.contains_key("error") {
+            return Err(Error::Invalid("response must not contain both a result and an error"));
+        }
+
+        let msg = if o.contains_key("method") {
+            Muxed::Request(serde_json::from_value(Value::Object(o))?)
+        } else if let Some(result) = o.remove("result") {
+            Muxed::Response(Ok(serde_json::from_value(result)?))
+        } else if let Some(error) = o.remove("error") {
+            Muxed::Response(Err(serde_json::from_value(error)?))
+        } else {
+            return Err(Error::Invalid("message must contain a method, result or error"));
+        };
+
+        if id.is_none() && matches!(msg, Muxed::Response(_)) {
+            return Err(Error::Invalid("responses must have an id"));
+        }
+
+        Ok((id, msg))
+    }
+
+    /// Encode a batch of messages to a JSON array
+    pub fn encode_batch(
+        &self, msgs: &[(Option<Id>, Message<Req, Resp>)],
+    ) -> Result<Vec<u8>, Error> {
+        let batch = msgs
+            .iter()
+            .map(|(id, m)| self.encode_value(id, m))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(serde_json::to_vec(&batch)?)
+    }
+
+    /// Decode a single message or batch array, returning a result for each entry in the batch
+    #[allow(clippy::type_complexity)]
+    pub fn decode_batch(
+        &self, data: &[u8],
+    ) -> Result<Vec<Result<(Option<Id>, Message<Req, Resp>), Error>>, Error> {
+        match serde_json::from_slice(data)? {
+            Value::Array(a) if a.is_empty() => Err(Error::Invalid("batch must not be empty")),
+            Value::Array(a) => Ok(a.into_iter().map(|v| self.decode_value(v)).collect()),
+            v => Ok(vec![self.decode_value(v)]),
+        }
+    }
+}
+
+impl<Req, Resp> Default for JsonRpcCodec<Req, Resp>
+where
+    Req: Serialize + DeserializeOwned,
+    Resp: Serialize + DeserializeOwned,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Req, Resp> Clone for JsonRpcCodec<Req, Resp> {
+    fn clone(&self) -> Self {
+        JsonRpcCodec {
+            _req: PhantomData,
+            _resp: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp> Codec<Option<Id>, Req, Result<Resp, RpcError>> for JsonRpcCodec<Req, Resp>
+where
+    Req: Serialize + DeserializeOwned,
+    Resp: Serialize + DeserializeOwned,
+{
+    type Error = Error;
+
+    fn encode(
+        &self, id: &Option<Id>, msg: &Message<Req, Resp>,
+    ) -> Result<Vec<u8>, Error> {
+        let v = self.encode_value(id, msg)?;
+        Ok(serde_json::to_vec(&v)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(Option<Id>, Message<Req, Resp>), Error> {
+        let v = serde_json::from_slice(data)?;
+        self.decode_value(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::prelude::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::connector::Connector;
+    use crate::mux::Mux;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "method", content = "params")]
+    #[serde(rename_all = "snake_case")]
+    enum Req {
+        Add(i64, i64),
+        Log { msg: String },
+    }
+
+    type Msg = Muxed<Req, Result<i64, RpcError>>;
+
+    fn codec() -> JsonRpcCodec<Req, i64> {
+        JsonRpcCodec::new()
+    }
+
+    #[test]
+    fn test_jsonrpc_request() {
+        let m: Msg = Muxed::Request(Req::Add(1, 2));
+        let v = codec().encode_value(&Some(Id::from(1)), &m).unwrap();
+        assert_eq!(v, json!({"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1}));
+
+        let d = codec().decode(&serde_json::to_vec(&v).unwrap()).unwrap();
+        assert_eq!(d, (Some(Id::from(1)), m));
+    }
+
+    #[test]
+    fn test_jsonrpc_notification() {
+        let m: Msg = Muxed::Request(Req::Log { msg: "hello".to_string() });
+        let v = codec().encode_value(&None, &m).unwrap();
+        assert_eq!(v, json!({"jsonrpc": "2.0", "method": "log", "params": {"msg": "hello"}}));
+
+        assert_eq!(codec().decode_value(v).unwrap(), (None, m));
+    }
+
+    #[test]
+    fn test_jsonrpc_response() {
+        let m: Msg = Muxed::Response(Ok(3));
+        let v = codec().encode_value(&Some(Id::from("a")), &m).unwrap();
+        assert_eq!(v, json!({"jsonrpc": "2.0", "result": 3, "id": "a"}));
+        assert_eq!(codec().decode_value(v).unwrap(), (Some(Id::from("a")), m));
+
+        let m: Msg = Muxed::Response(Err(RpcError::new(RpcError::METHOD_NOT_FOUND, "Method not found")));
+        let v = codec().encode_value(&Some(Id::Null), &m).unwrap();
+        assert_eq!(
+            v,
+            json!({"jsonrpc": "2.0", "error": {"code": -32601, "message": "Method not found"}, "id": null})
+        );
+
+        // Null IDs are distinct from the missing ID of a notification
+        assert_eq!(codec().decode_value(v).unwrap(), (Some(Id::Null), m.clone()));
+        assert!(codec().encode_value(&None, &m).is_err());
+        assert!(codec().decode_value(json!({"jsonrpc": "2.0", "result": 3})).is_err());
+
+        // Responses may not carry both a result and an error
+        let v = json!({"jsonrpc": "2.0", "result": 3, "error": {"code": -32603, "message": "Internal error"}, "id": 1});
+        let e = codec().decode_value(v).unwrap_err();
+        assert_eq!(e.to_rpc_error().code, RpcError::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_jsonrpc_batch() {
+        let data = br#"[
+            {"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1},
+            {"jsonrpc": "2.0", "method": "log", "params": {"msg": "hi"}},
+            {"foo": "boo"},
+            {"jsonrpc": "2.0", "result": 7, "id": 2}
+        ]"#;
+
+        let d = codec().decode_batch(data).unwrap();
+        assert_eq!(d.len(), 4);
+        assert_eq!(d[0].as_ref().unwrap(), &(Some(Id::from(1)), Muxed::Request(Req::Add(1, 2))));
+        assert_eq!(d[1].as_ref().unwrap(), &(None, Muxed::Request(Req::Log { msg: "hi".to_string() })));
+        assert_eq!(d[2].as_ref().unwrap_err().to_rpc_error().code, RpcError::INVALID_REQUEST);
+        assert_eq!(d[3].as_ref().unwrap(), &(Some(Id::from(2)), Muxed::Response(Ok(7))));
+
+        let e = codec().decode_batch(b"[]").unwrap_err();
+        assert_eq!(e.to_rpc_error().code, RpcError::INVALID_REQUEST);
+
+        let e = codec().decode_batch(b"[{").unwrap_err();
+        assert_eq!(e.to_rpc_error().code, RpcError::PARSE_ERROR);
+
+        let msgs = vec![(Some(Id::from(1)), Muxed::Response(Ok(3))), (Some(Id::from(2)), Muxed::Response(Ok(4)))];
+        let encoded = codec().encode_batch(&msgs).unwrap();
+        let v: Value = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(
+            v,
+            json!([{"jsonrpc": "2.0", "result": 3, "id": 1}, {"jsonrpc": "2.0", "result": 4, "id": 2}])
+        );
+    }
+
+    #[test]
+    fn test_jsonrpc_mux() {
+        let mut mux: Mux<Option<Id>, u32, Req, Result<i64, RpcError>, (), ()> = Mux::new(|_| ());
+        let c = codec();
+
+        // Client request via the mux
+        let mut m = mux.clone();
+        let a = async move {
+            let r = m.request((), Some(Id::from(1)), 0, Req::Add(2, 3)).await.unwrap();
+            assert_eq!(r, Ok(5));
+
+            // Notifications are sent without awaiting a response
+            m.notify((), None, 0, Req::Log { msg: "hi".to_string() }).await.unwrap();
+            assert_eq!(m.pending(), 0);
+
+            let r = m.request((), Some(Id::from(2)), 0, Req::Add(1, 1)).await.unwrap();
+            assert_eq!(r, Ok(2));
+        }
+        .boxed();
+
+        // Encode outgoing requests, handle with a standard JSON-RPC peer, and decode responses
+        let b = async move {
+            while let Some((id, addr, msg, _ctx)) = mux.next().await {
+                let req = c.encode(&id, &msg).unwrap();
+                let req: Value = serde_json::from_slice(&req).unwrap();
+                if id.is_none() {
+                    assert_eq!(req, json!({"jsonrpc": "2.0", "method": "log", "params": {"msg": "hi"}}));
+                    continue;
+                }
+                assert_eq!(req["method"], "add");
+
+                let sum = req["params"][0].as_i64().unwrap() + req["params"][1].as_i64().unwrap();
+                let resp = json!({"jsonrpc": "2.0", "result": sum, "id": req["id"]});
+
+                let (id, resp) = c.decode(&serde_json::to_vec(&resp).unwrap()).unwrap();
+                mux.handle(id, addr, resp).unwrap();
+            }
+        }
+        .boxed();
+
+        let _ = block_on(future::select(a, b));
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::prelude::*;
+
+use crate::clock::{Clock, SystemClock};
+use crate::connector::Connector;
+use crate::mux::Mux;
+use crate::stream::Error;
+
+/// Keepalive configures periodic liveness probing of a connected peer.
+/// Ping requests are generated by the provided function and sent at each interval,
+/// these must be answered by the remote peer as with any other request.
+/// If the peer fails to answer `max_missed` consecutive pings it is considered dead,
+/// and the connection is torn down.
+pub struct Keepalive<ReqId, Req> {
+    interval: Duration,
+    timeout: Duration,
+    max_missed: usize,
+    ping: Arc<dyn Fn() -> (ReqId, Req) + Send + Sync>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<ReqId, Req> Clone for Keepalive<ReqId, Req> {
+    fn clone(&self) -> Self {
+        Keepalive {
+            interval: self.interval,
+            timeout: self.timeout,
+            max_missed: self.max_missed,
+            ping: self.ping.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<ReqId, Req> Keepalive<ReqId, Req>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+{
+    /// Create a new keepalive configuration with the provided interval and ping generator,
+    /// returning a request ID and ping payload for each probe.
+    /// By default pings time out after one interval and a peer is dead after three missed pings
+    pub fn new<F>(interval: Duration, ping: F) -> Self
+    where
+        F: Fn() -> (ReqId, Req) + Send + Sync + 'static,
+    {
+        Keepalive {
+            interval,
+            timeout: interval,
+            max_missed: 3,
+            ping: Arc::new(ping),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set the timeout for each ping
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the number of consecutive missed pings after which the peer is considered dead
+    pub fn with_max_missed(mut self, max_missed: usize) -> Self {
+        self.max_missed = max_missed.max(1);
+        self
+    }
+
+    /// Set the clock used to schedule pings and time out responses
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Probe the peer until it is considered dead, returning the error to tear down the connection with
+    pub(crate) async fn run<Target, Resp, Ctx>(
+        self, mut mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>, target: Target, ctx: Ctx,
+    ) -> Error
+    where
+        Target: Debug + Clone + Send + 'static,
+        Resp: Debug + Send + 'static,
+        Ctx: Debug + Clone + Send + 'static,
+    {
+        let mut missed = 0;
+
+        loop {
+            self.clock.sleep(self.interval).await;
+
+            let (id, ping) = (self.ping)();
+            let req = mux.request(ctx.clone(), id.clone(), target.clone(), ping);
+
+            let res = match future::select(req, self.clock.sleep(self.timeout)).await {
+                future::Either::Left((r, _)) => Some(r),
+                future::Either::Right(_) => None,
+            };
+
+            match res {
+                Some(Ok(_)) => {
+                    missed = 0;
+                    continue;
+                }
+                // A ping reusing the ID of a pending request was never sent, so counts as missed
+                Some(Err(Error::DuplicateId(_))) => missed += 1,
+                Some(Err(e)) => return e,
+                None => {
+                    mux.cancel(&id, Error::KeepaliveTimeout);
+                    missed += 1;
+                }
+            }
+
+            debug!("Keepalive to {:?} missed ({}/{})", target, missed, self.max_missed);
+
+            if missed >= self.max_missed {
+                warn!("Keepalive to {:?} failed, closing connection", target);
+                return Error::KeepaliveTimeout;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures::executor::LocalPool;
+    use futures::task::LocalSpawnExt;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::LengthPrefix;
+    use crate::stream::StreamConnector;
+
+    type Conn = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, ()>;
+
+    const PING: &[u8] = b"ping";
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    fn keepalive(clock: VirtualClock) -> Keepalive<u16, Vec<u8>> {
+        let index = Arc::new(AtomicU16::new(0x8000));
+        Keepalive::new(INTERVAL, move || (index.fetch_add(1, Ordering::SeqCst), PING.to_vec())).with_clock(clock)
+    }
+
+    #[test]
+    fn test_keepalive_alive() {
+        let (clock, mut pool) = (VirtualClock::new(), LocalPool::new());
+        let mut mux: Mux<u16, u32, Vec<u8>, Vec<u8>, Error, ()> = Mux::new(|_| Error::Closed);
+
+        let ping = keepalive(clock.clone()).run(mux.clone(), 0x22, ());
+        let mut driver = pool.spawner().spawn_local_with_handle(ping).unwrap();
+        pool.run_until_stalled();
+
+        // Connection remains up while pings are answered, tolerating fewer than max_missed misses
+        for i in 0..10 {
+            clock.advance(INTERVAL);
+            let (id, target, msg, _ctx) = pool.run_until(mux.next()).unwrap();
+            assert_eq!(msg.req(), Some(PING.to_vec()));
+
+            match i % 3 {
+                0 => clock.advance(INTERVAL),
+                _ => mux.handle_resp(id, target, PING.to_vec()).unwrap(),
+            }
+            pool.run_until_stalled();
+            assert!((&mut driver).now_or_never().is_none());
+        }
+    }
+
+    // Connect a client with keepalives enabled to a server which never answers pings,
+    // returning the client connector and the result of the client driver
+    async fn connect(clock: VirtualClock) -> (Conn, task::JoinHandle<Result<(), Error>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        task::spawn(async move {
+            let (s, _) = listener.accept().await.unwrap();
+            let (mut conn, driver): (Conn, _) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 0x22, ());
+            task::spawn(driver.run());
+
+            while conn.next().await.is_some() {}
+        });
+
+        let s = TcpStream::connect(addr).await.unwrap();
+        let (conn, driver) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 0x11, ());
+        let driver = task::spawn(driver.with_keepalive(keepalive(clock)).run());
+
+        (conn, driver)
+    }
+
+    #[test]
+    fn test_keepalive_dead() {
+        task::block_on(async {
+            let clock = VirtualClock::new();
+            let (conn, driver) = connect(clock.clone()).await;
+
+            // Pending requests fail once the peer is declared dead
+            let mut c = conn.clone();
+            let pending = task::spawn(async move { c.request((), 1, 0x11, vec![1]).await });
+
+            let advance = async {
+                loop {
+                    clock.advance(INTERVAL);
+                    task::yield_now().await;
+                }
+            };
+            let res = match future::select(driver, advance.boxed()).await {
+                future::Either::Left((r, _)) => r,
+                future::Either::Right(_) => unreachable!(),
+            };
+
+            assert!(matches!(res, Err(Error::KeepaliveTimeout)));
+            assert!(matches!(pending.await, Err(Error::ConnectionLost)));
+            assert!(conn.is_closed());
+        });
+    }
+}
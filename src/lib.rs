@@ -17,13 +17,86 @@ pub mod mux;
 /// Mux is an implementation of a Connector using a HashMap and oneshot channels
 pub use crate::mux::Mux;
 
+pub mod dedup;
+/// Dedup configures filtering of duplicated or replayed incoming requests by a Mux
+pub use dedup::{Dedup, DedupPolicy};
+
+pub mod cache;
+/// ResponseCache answers retransmitted requests with previously sent responses
+pub use cache::ResponseCache;
+
+pub mod single_flight;
+/// SingleFlight coalesces concurrent identical requests into a single underlying request
+pub use single_flight::SingleFlight;
+
 pub mod mapped;
 /// Mapped converts a connector interface from one type to another using a Mapper implementation.
 /// This can be used to multiplex protocols / message types over a single base connector
 pub use mapped::{Mapped, Mapper};
 
+pub mod clock;
+/// Clock abstracts time for simulated delays, VirtualClock allows delays to be advanced manually in tests
+pub use clock::{Clock, SystemClock, VirtualClock};
+#[cfg(feature = "faults")]
+pub use clock::Jitter;
+
 /// Mock is a mock connector implementation that allows expectation based testing of modules that consume
 /// the Connector interface
 pub mod mock;
 
 pub mod wire;
+/// Wire is an in-memory interconnect for integration testing, with configurable per-link faults
+pub use wire::{Link, Wire};
+
+pub mod codec;
+/// Codec defines a byte serialisation boundary for Muxed messages and their request IDs,
+/// EnvelopeCodec provides the default envelope layout
+pub use codec::{Codec, CodecError, EnvelopeCodec};
+
+/// Serde based codecs, enabled using the `bincode`, `cbor` and `json` features
+#[cfg(any(feature = "bincode", feature = "cbor", feature = "json"))]
+pub mod serde_codec;
+
+/// Protobuf envelope codec for interoperability with non-Rust peers, enabled using the `prost` feature
+#[cfg(feature = "prost")]
+pub mod prost_codec;
+
+/// JSON-RPC 2.0 compatible codec, enabled using the `jsonrpc` feature
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
+
+pub mod framing;
+/// Framing splits byte streams into discrete frames using length prefix, COBS or newline delimiters
+pub use framing::{Cobs, Framing, LengthPrefix, Newline};
+
+pub mod stream;
+/// StreamConnector provides a Connector over any AsyncRead + AsyncWrite byte stream
+pub use stream::{StreamConnector, StreamDriver};
+
+/// WebSocket transport for StreamConnector, enabled using the `websocket` feature
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// TLS support for stream transports using rustls, enabled using the `tls` feature
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub mod handshake;
+/// Handshake defines application level peer authentication run before a stream connection goes live
+pub use handshake::{Handshake, Role};
+
+pub mod sealed;
+/// Sealed provides per-message authentication or encryption of connector messages using per-target keys
+pub use sealed::{Sealed, Sealer};
+
+pub mod pool;
+/// MuxPool manages per-target connections, routing requests and responses by Target
+pub use pool::MuxPool;
+
+pub mod reconnect;
+/// Reconnecting provides a Connector that redials lost connections with backoff
+pub use reconnect::{Backoff, Reconnecting};
+
+pub mod keepalive;
+/// Keepalive configures periodic liveness probing for stream connectors
+pub use keepalive::Keepalive;
@@ -0,0 +1,319 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use super::{Matcher, MockConnector};
+use crate::connector::Connector;
+use crate::muxed::Muxed;
+
+/// Inbound is a scripted inbound message, with the response expected for requests
+struct Inbound<ReqId, Target, Req, Resp, Ctx> {
+    id: ReqId,
+    from: Target,
+    msg: Muxed<Req, Resp>,
+    ctx: Ctx,
+    expected: Option<Matcher<Resp>>,
+    expected_ctx: Option<Ctx>,
+}
+
+/// Outstanding is an emitted inbound request awaiting a response
+#[derive(Debug)]
+struct Outstanding<ReqId, Target, Resp, Ctx> {
+    id: ReqId,
+    from: Target,
+    expected: Matcher<Resp>,
+    ctx: Option<Ctx>,
+}
+
+struct Script<ReqId, Target, Req, Resp, Ctx> {
+    inbound: VecDeque<Inbound<ReqId, Target, Req, Resp, Ctx>>,
+    outstanding: Vec<Outstanding<ReqId, Target, Resp, Ctx>>,
+}
+
+type SharedScript<ReqId, Target, Req, Resp, Ctx> = Arc<Mutex<Script<ReqId, Target, Req, Resp, Ctx>>>;
+
+/// MockIncoming is a mock for the incoming side of a connector, emitting scripted inbound messages
+/// via Stream in the same form as a Mux, and checking responses made to inbound requests via Connector::respond.
+///
+/// Outgoing requests are passed to an internal MockConnector, available via outgoing() to set expectations.
+/// The stream ends once all scripted messages have been emitted.
+pub struct MockIncoming<ReqId, Target, Req, Resp, E, Ctx> {
+    script: SharedScript<ReqId, Target, Req, Resp, Ctx>,
+    outgoing: MockConnector<Target, Req, Resp, E, Ctx>,
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx> Clone for MockIncoming<ReqId, Target, Req, Resp, E, Ctx> {
+    fn clone(&self) -> Self {
+        MockIncoming {
+            script: self.script.clone(),
+            outgoing: self.outgoing.clone(),
+        }
+    }
+}
+
+// MockIncoming holds no pinned state, so may be polled regardless of type parameters
+impl<ReqId, Target, Req, Resp, E, Ctx> Unpin for MockIncoming<ReqId, Target, Req, Resp, E, Ctx> {}
+
+impl<ReqId, Target, Req, Resp, E, Ctx> MockIncoming<ReqId, Target, Req, Resp, E, Ctx>
+where
+    ReqId: PartialEq + Debug + Clone + Send + 'static,
+    Target: PartialEq + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    /// Create a new incoming mock
+    pub fn new() -> Self {
+        MockIncoming {
+            script: Arc::new(Mutex::new(Script {
+                inbound: VecDeque::new(),
+                outstanding: vec![],
+            })),
+            outgoing: MockConnector::new(),
+        }
+    }
+
+    /// Script an inbound request, expecting a response to the sender matching the provided
+    /// value, matcher or predicate
+    pub fn inbound_request<M>(&mut self, id: ReqId, from: Target, req: Req, ctx: Ctx, expected: M) -> &mut Self
+    where
+        M: Into<Matcher<Resp>>,
+    {
+        self.push(Inbound {
+            id,
+            from,
+            msg: Muxed::Request(req),
+            ctx,
+            expected: Some(expected.into()),
+            expected_ctx: None,
+        })
+    }
+
+    /// Script an inbound response, for code under test that consumes responses from the stream
+    pub fn inbound_response(&mut self, id: ReqId, from: Target, resp: Resp, ctx: Ctx) -> &mut Self {
+        self.push(Inbound {
+            id,
+            from,
+            msg: Muxed::Response(resp),
+            ctx,
+            expected: None,
+            expected_ctx: None,
+        })
+    }
+
+    /// Expect the response to the last scripted inbound request to be made with the provided context
+    pub fn expect_context(&mut self, ctx: Ctx) -> &mut Self {
+        match self.script.lock().unwrap().inbound.back_mut() {
+            Some(i) if i.expected.is_some() => i.expected_ctx = Some(ctx),
+            _ => panic!("expect_context must follow a scripted inbound request"),
+        }
+        self
+    }
+
+    fn push(&mut self, inbound: Inbound<ReqId, Target, Req, Resp, Ctx>) -> &mut Self {
+        self.script.lock().unwrap().inbound.push_back(inbound);
+        self
+    }
+
+    /// Fetch the mock connector handling outgoing requests, used to set outgoing expectations
+    pub fn outgoing(&self) -> MockConnector<Target, Req, Resp, E, Ctx> {
+        self.outgoing.clone()
+    }
+
+    /// Finalise the mock, checking that all scripted messages have been emitted and answered
+    /// and that outgoing expectations have been met
+    pub fn finalise(&mut self) {
+        let (pending, outstanding) = {
+            let mut s = self.script.lock().unwrap();
+            let pending: Vec<_> = s.inbound.drain(..).map(|i| format!("{:?} from {:?}", i.msg, i.from)).collect();
+            (pending, std::mem::take(&mut s.outstanding))
+        };
+
+        if !pending.is_empty() {
+            panic!("not all inbound messages have been received, remaining: {:#?}", pending);
+        }
+        if !outstanding.is_empty() {
+            panic!("not all inbound requests have been answered, remaining: {:#?}", outstanding);
+        }
+
+        self.outgoing.finalise();
+    }
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx> Default for MockIncoming<ReqId, Target, Req, Resp, E, Ctx>
+where
+    ReqId: PartialEq + Debug + Clone + Send + 'static,
+    Target: PartialEq + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, E, Ctx> Connector<ReqId, Target, Req, Resp, E, Ctx>
+    for MockIncoming<ReqId, Target, Req, Resp, E, Ctx>
+where
+    ReqId: PartialEq + Debug + Send + 'static,
+    Target: PartialEq + Debug + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    /// Make an outgoing request via the outgoing mock connector
+    async fn request(&mut self, ctx: Ctx, id: ReqId, addr: Target, req: Req) -> Result<Resp, E> {
+        self.outgoing.request(ctx, id, addr, req).await
+    }
+
+    /// Respond to an inbound request, checking the response against the scripted expectation
+    async fn respond(&mut self, ctx: Ctx, id: ReqId, addr: Target, resp: Resp) -> Result<(), E> {
+        let outstanding = {
+            let mut s = self.script.lock().unwrap();
+            let index = s.outstanding.iter().position(|o| o.id == id && o.from == addr);
+            index.map(|i| s.outstanding.remove(i))
+        };
+
+        let outstanding = match outstanding {
+            Some(o) => o,
+            None => panic!("unexpected response {:?} to {:?} (id: {:?})", resp, addr, id),
+        };
+
+        assert!(
+            outstanding.expected.matches(&resp),
+            "response mismatch (id: {:?}, expected: {:?}, actual: {:?})",
+            id,
+            outstanding.expected,
+            resp
+        );
+        if let Some(c) = &outstanding.ctx {
+            assert_eq!(c, &ctx, "context mismatch");
+        }
+
+        Ok(())
+    }
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx> Stream for MockIncoming<ReqId, Target, Req, Resp, E, Ctx>
+where
+    ReqId: Clone,
+    Target: Clone,
+{
+    type Item = (ReqId, Target, Muxed<Req, Resp>, Ctx);
+
+    // Emit the next scripted message, recording the expected response for requests
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut s = self.script.lock().unwrap();
+
+        let inbound = match s.inbound.pop_front() {
+            Some(i) => i,
+            None => return Poll::Ready(None),
+        };
+
+        if let Some(expected) = inbound.expected {
+            s.outstanding.push(Outstanding {
+                id: inbound.id.clone(),
+                from: inbound.from.clone(),
+                expected,
+                ctx: inbound.expected_ctx,
+            });
+        }
+
+        Poll::Ready(Some((inbound.id, inbound.from, inbound.msg, inbound.ctx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mock::{any, eq, MockTransaction};
+
+    type Incoming = MockIncoming<u16, u32, u8, u8, (), ()>;
+
+    // Handler under test, answering inbound requests with the request plus the result of an outgoing request
+    async fn handler<C>(mut c: C)
+    where
+        C: Connector<u16, u32, u8, u8, (), ()> + Stream<Item = (u16, u32, Muxed<u8, u8>, ())> + Unpin,
+    {
+        while let Some((id, from, msg, ctx)) = c.next().await {
+            if let Muxed::Request(req) = msg {
+                let offset = c.request((), id, 0x01, req).await.unwrap();
+                c.respond(ctx, id, from, req + offset).await.unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_mock_incoming() {
+        let mut m = Incoming::new();
+        m.inbound_request(1, 0x11, 10, (), eq(11));
+        m.inbound_response(7, 0x22, 70, ());
+        m.inbound_request(2, 0x22, 20, (), |r: &u8| *r > 20);
+
+        m.outgoing()
+            .expect(vec![MockTransaction::request(0x01, 10, Ok((1, ()))), MockTransaction::request(0x01, 20, Ok((2, ())))]);
+
+        block_on(handler(m.clone()));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "response mismatch")]
+    fn test_mock_incoming_mismatch() {
+        let mut m = Incoming::new();
+        m.inbound_request(1, 0x11, 10, (), eq(12));
+        m.outgoing().expect(vec![MockTransaction::request(0x01, 10, Ok((1, ())))]);
+
+        block_on(handler(m.clone()));
+    }
+
+    #[test]
+    fn test_mock_incoming_context() {
+        let mut m = MockIncoming::<u16, u32, u8, u8, (), u32>::new();
+        m.inbound_request(1, 0x11, 10, 0xaa, eq(11)).expect_context(0xbb).inbound_request(2, 0x11, 20, 0xcc, any());
+
+        let (id, from, _, _) = block_on(m.next()).unwrap();
+        block_on(m.respond(0xbb, id, from, 11)).unwrap();
+
+        // Responses to requests without an expected context may use any context
+        let (id, from, _, _) = block_on(m.next()).unwrap();
+        block_on(m.respond(0xdd, id, from, 21)).unwrap();
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "context mismatch")]
+    fn test_mock_incoming_context_mismatch() {
+        let mut m = MockIncoming::<u16, u32, u8, u8, (), u32>::new();
+        m.inbound_request(1, 0x11, 10, 0xaa, eq(11)).expect_context(0xbb);
+
+        let (id, from, _, ctx) = block_on(m.next()).unwrap();
+        let _ = block_on(m.respond(ctx, id, from, 11));
+    }
+
+    #[test]
+    #[should_panic(expected = "not all inbound requests have been answered")]
+    fn test_mock_incoming_unanswered() {
+        let mut m = Incoming::new();
+        m.inbound_request(1, 0x11, 10, (), eq(11));
+
+        // Receive the request without responding
+        let _ = block_on(m.next());
+
+        m.finalise();
+    }
+}
@@ -0,0 +1,1355 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::prelude::*;
+#[cfg(feature = "faults")]
+use rand::{rngs::StdRng, SeedableRng};
+
+use derive_builder::Builder;
+
+#[cfg(feature = "faults")]
+use crate::clock::Jitter;
+use crate::clock::{Clock, SystemClock};
+use crate::connector::Connector;
+use crate::muxed::Muxed;
+
+mod incoming;
+pub use self::incoming::MockIncoming;
+
+mod transcript;
+pub use self::transcript::{Call, Recorder, Transcript};
+use self::transcript::{Record, Recording};
+
+/// Matcher is a predicate used to match expected addresses, requests and responses
+pub struct Matcher<T> {
+    description: String,
+    f: Arc<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+impl<T> Matcher<T> {
+    /// Create a new matcher from a predicate function, with a description used when reporting mismatches
+    pub fn new<F>(description: &str, f: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        Matcher {
+            description: description.to_string(),
+            f: Arc::new(f),
+        }
+    }
+
+    /// Check whether a value matches
+    pub fn matches(&self, v: &T) -> bool {
+        (self.f)(v)
+    }
+}
+
+impl<T> Clone for Matcher<T> {
+    fn clone(&self) -> Self {
+        Matcher {
+            description: self.description.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<T> Debug for Matcher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+// Matchers are equal only where they share a predicate
+impl<T> PartialEq for Matcher<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.f, &other.f)
+    }
+}
+
+impl<T, F> From<F> for Matcher<T>
+where
+    F: Fn(&T) -> bool + Send + Sync + 'static,
+{
+    fn from(f: F) -> Self {
+        Matcher::new("<predicate>", f)
+    }
+}
+
+/// Create a matcher accepting any value
+pub fn any<T>() -> Matcher<T> {
+    Matcher::new("<any>", |_| true)
+}
+
+/// Create a matcher accepting values equal to the provided value
+pub fn eq<T>(v: T) -> Matcher<T>
+where
+    T: PartialEq + Debug + Send + Sync + 'static,
+{
+    Matcher::new(&format!("{:?}", v), move |a| a == &v)
+}
+
+/// MockId is a type erased request ID, allowing expectations and transcripts to hold IDs independent of the
+/// ID type used by the connector
+#[derive(Clone)]
+pub struct MockId {
+    description: String,
+    id: Arc<Mutex<dyn Any + Send>>,
+}
+
+impl MockId {
+    /// Create a new type erased request ID
+    pub fn new<Id>(id: Id) -> Self
+    where
+        Id: Debug + Send + 'static,
+    {
+        MockId {
+            description: format!("{:?}", id),
+            id: Arc::new(Mutex::new(id)),
+        }
+    }
+
+    /// Check whether a request ID matches, IDs of a different type never match
+    pub fn matches<Id: PartialEq + 'static>(&self, id: &Id) -> bool {
+        self.id.lock().unwrap().downcast_ref::<Id>().map(|v| v == id).unwrap_or(false)
+    }
+}
+
+impl Debug for MockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl PartialEq for MockId {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.id, &other.id)
+    }
+}
+
+/// Expected is an expected address, request or response, either a value compared for equality or a matcher.
+/// Values carry the equality function captured on construction, so matching requires no PartialEq bound
+#[derive(Clone)]
+enum Expected<T> {
+    Value(T, fn(&T, &T) -> bool),
+    Matching(Matcher<T>),
+}
+
+impl<T> Expected<T> {
+    fn value(v: T) -> Self
+    where
+        T: PartialEq,
+    {
+        Expected::Value(v, T::eq)
+    }
+
+    fn matches(&self, v: &T) -> bool {
+        match self {
+            Expected::Value(e, eq) => eq(e, v),
+            Expected::Matching(m) => m.matches(v),
+        }
+    }
+}
+
+impl<T> PartialEq for Expected<T> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expected::Value(a, eq), Expected::Value(b, _)) => eq(a, b),
+            (Expected::Matching(a), Expected::Matching(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Debug> Debug for Expected<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Value(v, _) => write!(f, "{:?}", v),
+            Expected::Matching(m) => write!(f, "{:?}", m),
+        }
+    }
+}
+
+// Check an optional expected ID against the actual request ID
+fn id_matches<Id: PartialEq + 'static>(expected: &Option<MockId>, id: &Id) -> bool {
+    expected.as_ref().map(|e| e.matches(id)).unwrap_or(true)
+}
+
+/// Responder computes a mock response from the actual request
+pub type Responder<Addr, Req, Resp, Ctx, E> = Arc<dyn Fn(&Ctx, &Addr, &Req) -> Result<Resp, E> + Send + Sync>;
+
+/// Reply is the response provided by a mock request expectation
+pub enum Reply<Addr, Req, Resp, Ctx, E> {
+    /// A fixed response
+    Fixed(Result<(Resp, Ctx), E>),
+    /// A response computed from the actual request
+    Dynamic(Responder<Addr, Req, Resp, Ctx, E>),
+}
+
+impl<Addr, Req, Resp, Ctx, E> Reply<Addr, Req, Resp, Ctx, E> {
+    /// Resolve the reply for the actual request, returning the response and context.
+    /// Fixed replies return the mocked context, and dynamic replies the context of the request
+    fn resolve(self, ctx: &Ctx, addr: &Addr, req: &Req) -> Result<(Resp, Ctx), E>
+    where
+        Ctx: Clone,
+    {
+        match self {
+            Reply::Fixed(r) => r,
+            Reply::Dynamic(f) => f(ctx, addr, req).map(|resp| (resp, ctx.clone())),
+        }
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> Clone for Reply<Addr, Req, Resp, Ctx, E>
+where
+    Resp: Clone,
+    Ctx: Clone,
+    E: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Reply::Fixed(r) => Reply::Fixed(r.clone()),
+            Reply::Dynamic(f) => Reply::Dynamic(f.clone()),
+        }
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> Debug for Reply<Addr, Req, Resp, Ctx, E>
+where
+    Resp: Debug,
+    Ctx: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reply::Fixed(r) => write!(f, "{:?}", r),
+            Reply::Dynamic(_) => write!(f, "<dynamic>"),
+        }
+    }
+}
+
+// Dynamic replies are equal only where they share a responder
+impl<Addr, Req, Resp, Ctx, E> PartialEq for Reply<Addr, Req, Resp, Ctx, E>
+where
+    Resp: PartialEq,
+    Ctx: PartialEq,
+    E: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Reply::Fixed(a), Reply::Fixed(b)) => a == b,
+            (Reply::Dynamic(a), Reply::Dynamic(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// MockRequest is a mocked request expectation with a provided response
+#[derive(Debug, Clone, PartialEq, Builder)]
+pub struct MockRequest<Addr, Req, Resp, Ctx, E> {
+    #[builder(private, setter(name = "to_expected"))]
+    to: Expected<Addr>,
+    #[builder(private, setter(name = "req_expected"))]
+    req: Expected<Req>,
+    ctx: Option<Ctx>,
+    #[builder(default)]
+    id: Option<MockId>,
+
+    #[builder(private, setter(name = "reply"))]
+    resp: Reply<Addr, Req, Resp, Ctx, E>,
+    delay: Option<Duration>,
+}
+
+impl<Addr, Req, Resp, Ctx, E> MockRequestBuilder<Addr, Req, Resp, Ctx, E>
+where
+    Addr: Clone,
+    Req: Clone,
+    Resp: Clone,
+    Ctx: Clone,
+    E: Clone,
+{
+    pub fn to(&mut self, to: Addr) -> &mut Self
+    where
+        Addr: PartialEq,
+    {
+        self.to_expected(Expected::value(to))
+    }
+
+    /// Match the request address using the provided matcher
+    pub fn to_matching(&mut self, to: Matcher<Addr>) -> &mut Self {
+        self.to_expected(Expected::Matching(to))
+    }
+
+    pub fn req(&mut self, req: Req) -> &mut Self
+    where
+        Req: PartialEq,
+    {
+        self.req_expected(Expected::value(req))
+    }
+
+    /// Match the request using the provided matcher
+    pub fn req_matching(&mut self, req: Matcher<Req>) -> &mut Self {
+        self.req_expected(Expected::Matching(req))
+    }
+
+    pub fn resp(&mut self, resp: Result<(Resp, Ctx), E>) -> &mut Self {
+        self.reply(Reply::Fixed(resp))
+    }
+
+    /// Compute the response from the actual request using the provided function
+    pub fn resp_with<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Ctx, &Addr, &Req) -> Result<Resp, E> + Send + Sync + 'static,
+    {
+        self.reply(Reply::Dynamic(Arc::new(f)))
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> MockRequest<Addr, Req, Resp, Ctx, E> {
+    /// Create a new mock request.
+    /// You probably want to use MockTransaction::request instead of constructing this directly
+    pub fn new(to: Addr, req: Req, resp: Result<(Resp, Ctx), E>) -> Self
+    where
+        Addr: PartialEq,
+        Req: PartialEq,
+    {
+        Self::expected(Expected::value(to), Expected::value(req), Reply::Fixed(resp))
+    }
+
+    /// Create a new mock request using matchers for the address and request.
+    /// You probably want to use MockTransaction::request_matching instead of constructing this directly
+    pub fn matching(to: Matcher<Addr>, req: Matcher<Req>, resp: Result<(Resp, Ctx), E>) -> Self {
+        Self::expected(Expected::Matching(to), Expected::Matching(req), Reply::Fixed(resp))
+    }
+
+    /// Create a new mock request using matchers for the address and request, with the response computed
+    /// from the actual request by the provided function.
+    /// You probably want to use MockTransaction::request_with instead of constructing this directly
+    pub fn dynamic<F>(to: Matcher<Addr>, req: Matcher<Req>, f: F) -> Self
+    where
+        F: Fn(&Ctx, &Addr, &Req) -> Result<Resp, E> + Send + Sync + 'static,
+    {
+        Self::expected(Expected::Matching(to), Expected::Matching(req), Reply::Dynamic(Arc::new(f)))
+    }
+
+    fn expected(to: Expected<Addr>, req: Expected<Req>, resp: Reply<Addr, Req, Resp, Ctx, E>) -> Self {
+        MockRequest {
+            to,
+            req,
+            resp,
+            ctx: None,
+            id: None,
+            delay: None,
+        }
+    }
+
+    pub fn with_context(mut self, ctx: Ctx) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// Expect the request to be made with the provided request ID
+    pub fn with_id<Id>(mut self, id: Id) -> Self
+    where
+        Id: Debug + Send + 'static,
+    {
+        self.id = Some(MockId::new(id));
+        self
+    }
+
+    /// Delay the response by the provided duration, overriding the connector latency
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// MockResponse is a mocked response expectation
+#[derive(Debug, Clone, PartialEq, Builder)]
+pub struct MockResponse<Addr, Resp, Ctx, E> {
+    #[builder(private, setter(name = "to_expected"))]
+    to: Expected<Addr>,
+    #[builder(private, setter(name = "resp_expected"))]
+    resp: Expected<Resp>,
+    err: Option<E>,
+    ctx: Option<Ctx>,
+    #[builder(default)]
+    id: Option<MockId>,
+}
+
+impl<Addr, Resp, Ctx, E> MockResponseBuilder<Addr, Resp, Ctx, E>
+where
+    Addr: Clone,
+    Resp: Clone,
+    Ctx: Clone,
+    E: Clone,
+{
+    pub fn to(&mut self, to: Addr) -> &mut Self
+    where
+        Addr: PartialEq,
+    {
+        self.to_expected(Expected::value(to))
+    }
+
+    /// Match the response address using the provided matcher
+    pub fn to_matching(&mut self, to: Matcher<Addr>) -> &mut Self {
+        self.to_expected(Expected::Matching(to))
+    }
+
+    pub fn resp(&mut self, resp: Resp) -> &mut Self
+    where
+        Resp: PartialEq,
+    {
+        self.resp_expected(Expected::value(resp))
+    }
+
+    /// Match the response using the provided matcher
+    pub fn resp_matching(&mut self, resp: Matcher<Resp>) -> &mut Self {
+        self.resp_expected(Expected::Matching(resp))
+    }
+}
+
+impl<Addr, Resp, Ctx, E> MockResponse<Addr, Resp, Ctx, E> {
+    /// Create a new mock response.
+    /// You probably want to use MockTransaction::response instead of constructing this directly
+    pub fn new(to: Addr, resp: Resp, err: Option<E>) -> Self
+    where
+        Addr: PartialEq,
+        Resp: PartialEq,
+    {
+        Self::expected(Expected::value(to), Expected::value(resp), err)
+    }
+
+    /// Create a new mock response using matchers for the address and response.
+    /// You probably want to use MockTransaction::response_matching instead of constructing this directly
+    pub fn matching(to: Matcher<Addr>, resp: Matcher<Resp>, err: Option<E>) -> Self {
+        Self::expected(Expected::Matching(to), Expected::Matching(resp), err)
+    }
+
+    fn expected(to: Expected<Addr>, resp: Expected<Resp>, err: Option<E>) -> Self {
+        MockResponse {
+            to,
+            resp,
+            err,
+            ctx: None,
+            id: None,
+        }
+    }
+
+    pub fn with_error(mut self, err: E) -> Self {
+        self.err = Some(err);
+        self
+    }
+
+    pub fn with_context(mut self, ctx: Ctx) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// Expect the response to be sent with the provided request ID
+    pub fn with_id<Id>(mut self, id: Id) -> Self
+    where
+        Id: Debug + Send + 'static,
+    {
+        self.id = Some(MockId::new(id));
+        self
+    }
+}
+
+// MockTransaction is a transaction expectation
+pub type MockTransaction<Addr, Req, Resp, Ctx, E> =
+    Muxed<MockRequest<Addr, Req, Resp, Ctx, E>, MockResponse<Addr, Resp, Ctx, E>>;
+
+impl<Addr, Req, Resp, Ctx, E> MockTransaction<Addr, Req, Resp, Ctx, E> {
+    /// Create a mock request -> response transaction
+    pub fn request(
+        to: Addr, req: Req, resp: Result<(Resp, Ctx), E>,
+    ) -> MockTransaction<Addr, Req, Resp, Ctx,  E>
+    where
+        Addr: PartialEq,
+        Req: PartialEq,
+    {
+        Muxed::Request(MockRequest::new(to, req, resp))
+    }
+
+    /// Create a mock request -> response transaction, matching the address and request using
+    /// the provided matchers or predicates
+    pub fn request_matching<A, R>(to: A, req: R, resp: Result<(Resp, Ctx), E>) -> MockTransaction<Addr, Req, Resp, Ctx, E>
+    where
+        A: Into<Matcher<Addr>>,
+        R: Into<Matcher<Req>>,
+    {
+        Muxed::Request(MockRequest::matching(to.into(), req.into(), resp))
+    }
+
+    /// Create a mock request -> response transaction, matching the address and request using
+    /// the provided matchers or predicates and computing the response from the actual request
+    pub fn request_with<A, R, F>(to: A, req: R, f: F) -> MockTransaction<Addr, Req, Resp, Ctx, E>
+    where
+        A: Into<Matcher<Addr>>,
+        R: Into<Matcher<Req>>,
+        F: Fn(&Ctx, &Addr, &Req) -> Result<Resp, E> + Send + Sync + 'static,
+    {
+        Muxed::Request(MockRequest::dynamic(to.into(), req.into(), f))
+    }
+
+    /// Create a mock response transaction
+    pub fn response(to: Addr, resp: Resp, err: Option<E>) -> MockTransaction<Addr, Req, Resp, Ctx, E>
+    where
+        Addr: PartialEq,
+        Resp: PartialEq,
+    {
+        Muxed::Response(MockResponse::new(to, resp, err))
+    }
+
+    /// Create a mock response transaction, matching the address and response using
+    /// the provided matchers or predicates
+    pub fn response_matching<A, R>(to: A, resp: R, err: Option<E>) -> MockTransaction<Addr, Req, Resp, Ctx, E>
+    where
+        A: Into<Matcher<Addr>>,
+        R: Into<Matcher<Resp>>,
+    {
+        Muxed::Response(MockResponse::matching(to.into(), resp.into(), err))
+    }
+}
+
+/// Mismatch records a call matching no expectation, collected where the connector is configured
+/// to return errors rather than panicking
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Kind of call, either request or response
+    pub kind: String,
+    /// Next outstanding expectations at the time of the call, empty where none remained
+    pub expected: Vec<String>,
+    /// Description of the actual call
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected.is_empty() {
+            true => writeln!(f, "unexpected {}", self.kind)?,
+            false => writeln!(f, "{} mismatch", self.kind)?,
+        }
+        for e in &self.expected {
+            writeln!(f, "-   {}", e)?;
+        }
+        write!(f, "+   {}", self.actual)
+    }
+}
+
+/// MismatchError produces the error returned to callers on a mismatch
+pub type MismatchError<E> = Arc<dyn Fn(&Mismatch) -> E + Send + Sync>;
+
+// Describe an actual call for mismatch reports
+fn describe(kind: &str, msg: &dyn Debug, addr: &dyn Debug, id: &dyn Debug, ctx: &dyn Debug) -> String {
+    format!("{} {:?} to {:?} (id: {:?}, ctx: {:?})", kind, msg, addr, id, ctx)
+}
+
+/// Unmatched describes the handling of a request matching no expectation
+enum Unmatched<Addr, Req, Resp, Ctx, E> {
+    Stub(Responder<Addr, Req, Resp, Ctx, E>),
+    Error(E),
+}
+
+/// Group is a sequence of expectations evaluated in order, optionally named
+struct Group<Addr, Req, Resp, Ctx, E> {
+    name: Option<String>,
+    transactions: VecDeque<MockTransaction<Addr, Req, Resp, Ctx, E>>,
+}
+
+/// Expectations holds the outstanding expectation groups for a connector.
+/// Each call is matched against the next expectation in each group, with requests matching no
+/// expectation answered by the stub where set, and other mismatches either panicking or being collected
+struct Expectations<Addr, Req, Resp, Ctx, E> {
+    ordered: bool,
+    groups: Vec<Group<Addr, Req, Resp, Ctx, E>>,
+    stub: Option<Responder<Addr, Req, Resp, Ctx, E>>,
+    on_mismatch: Option<MismatchError<E>>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<Addr, Req, Resp, Ctx, E> Expectations<Addr, Req, Resp, Ctx, E>
+where
+    Addr: Debug,
+    Req: Debug,
+    Resp: Debug,
+    Ctx: Debug,
+    E: Debug,
+{
+    // Take the next transaction for a call, using the provided function to match candidates.
+    // Where only one candidate exists this is returned regardless of matching so the caller may report mismatches
+    fn take<F>(&mut self, kind: &str, call: &dyn Debug, matches: F) -> MockTransaction<Addr, Req, Resp, Ctx, E>
+    where
+        F: Fn(&MockTransaction<Addr, Req, Resp, Ctx, E>) -> bool,
+    {
+        let candidates: Vec<_> = self
+            .groups
+            .iter()
+            .enumerate()
+            .filter_map(|(i, g)| g.transactions.front().map(|t| (i, t)))
+            .collect();
+
+        let index = match candidates.len() {
+            0 => panic!("{} error, no more transactions available ({}: {:?})", kind, kind, call),
+            1 => candidates[0].0,
+            _ => match candidates.iter().find(|(_, t)| matches(t)) {
+                Some((i, _)) => *i,
+                None => panic!(
+                    "{} error, no matching transaction ({}: {:?}, candidates: {:?})",
+                    kind,
+                    kind,
+                    call,
+                    candidates.iter().map(|(_, t)| t).collect::<Vec<_>>()
+                ),
+            },
+        };
+
+        self.groups[index].transactions.pop_front().unwrap()
+    }
+
+    // Take the next transaction matching a call, if one exists
+    fn find<F>(&mut self, matches: F) -> Option<MockTransaction<Addr, Req, Resp, Ctx, E>>
+    where
+        F: Fn(&MockTransaction<Addr, Req, Resp, Ctx, E>) -> bool,
+    {
+        let index = self.groups.iter().position(|g| g.transactions.front().map(&matches).unwrap_or(false))?;
+
+        self.groups[index].transactions.pop_front()
+    }
+
+    // Record a call matching no expectation, returning the error for the caller
+    fn mismatch(&mut self, on_mismatch: MismatchError<E>, kind: &str, actual: String) -> E {
+        let m = Mismatch {
+            kind: kind.to_string(),
+            expected: self.groups.iter().filter_map(|g| g.transactions.front()).map(|t| format!("{:?}", t)).collect(),
+            actual,
+        };
+
+        let err = on_mismatch(&m);
+        self.mismatches.push(m);
+        err
+    }
+
+    // Drain outstanding transactions, prefixed by their group names
+    fn drain(&mut self) -> Vec<String> {
+        self.groups
+            .drain(..)
+            .flat_map(|g| {
+                let name = g.name;
+                g.transactions.into_iter().map(move |t| match &name {
+                    Some(n) => format!("{}: {:?}", n, t),
+                    None => format!("{:?}", t),
+                })
+            })
+            .collect()
+    }
+}
+
+type Shared<Addr, Req, Resp, Ctx, E> = Arc<Mutex<Expectations<Addr, Req, Resp, Ctx, E>>>;
+
+type Recorded<Addr, Req, Resp, Ctx, E> = Arc<Mutex<Option<Box<dyn Record<Addr, Req, Resp, Ctx, E>>>>>;
+
+/// Timing configures simulated request latency
+struct Timing {
+    clock: Arc<dyn Clock>,
+    latency: Duration,
+    #[cfg(feature = "faults")]
+    jitter: Jitter,
+    #[cfg(feature = "faults")]
+    rng: StdRng,
+}
+
+impl Timing {
+    // Compute the delay for a request, using the expectation delay in place of the latency where set
+    #[cfg(feature = "faults")]
+    fn delay(&mut self, delay: Option<Duration>) -> Duration {
+        delay.unwrap_or(self.latency) + self.jitter.sample(&mut self.rng)
+    }
+
+    #[cfg(not(feature = "faults"))]
+    fn delay(&mut self, delay: Option<Duration>) -> Duration {
+        delay.unwrap_or(self.latency)
+    }
+}
+
+/// MockConnector provides an expectation based mock connector implementation
+/// to simplify writing tests against modules using the Connector abstraction.
+///
+/// By default expectations are evaluated strictly in order, unordered mode allows expectations to be met
+/// in any order, and named sequences allow independent ordered groups of expectations to be interleaved.
+///
+/// Request delays and latency are applied using the system clock by default, a VirtualClock may be provided
+/// to allow tests of timeout and retry logic to run deterministically.
+pub struct MockConnector<Addr, Req, Resp, E, Ctx> {
+    expectations: Shared<Addr, Req, Resp, Ctx, E>,
+    timing: Arc<Mutex<Timing>>,
+    contexts: Arc<Mutex<Vec<Ctx>>>,
+    recording: Recorded<Addr, Req, Resp, Ctx, E>,
+    _ctx: PhantomData<Ctx>,
+}
+
+impl<Addr, Req, Resp, E, Ctx> Clone for MockConnector<Addr, Req, Resp, E, Ctx> {
+    fn clone(&self) -> Self {
+        MockConnector {
+            expectations: self.expectations.clone(),
+            timing: self.timing.clone(),
+            contexts: self.contexts.clone(),
+            recording: self.recording.clone(),
+            _ctx: PhantomData,
+        }
+    }
+}
+
+impl<Addr, Req, Resp, E, Ctx> MockConnector<Addr, Req, Resp, E, Ctx>
+where
+    Addr: Debug + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    /// Create a new mock connector
+    pub fn new() -> MockConnector<Addr, Req, Resp, E, Ctx> {
+        MockConnector {
+            expectations: Arc::new(Mutex::new(Expectations {
+                ordered: true,
+                groups: vec![],
+                stub: None,
+                on_mismatch: None,
+                mismatches: vec![],
+            })),
+            timing: Arc::new(Mutex::new(Timing {
+                clock: Arc::new(SystemClock),
+                latency: Duration::from_secs(0),
+                #[cfg(feature = "faults")]
+                jitter: Jitter::None,
+                #[cfg(feature = "faults")]
+                rng: StdRng::from_entropy(),
+            })),
+            contexts: Arc::new(Mutex::new(vec![])),
+            recording: Arc::new(Mutex::new(None)),
+            _ctx: PhantomData,
+        }
+    }
+
+    /// Allow expectations set with expect() to be met in any order
+    pub fn unordered(self) -> Self {
+        self.expectations.lock().unwrap().ordered = false;
+        self
+    }
+
+    /// Set the clock used to apply request delays
+    pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.timing.lock().unwrap().clock = Arc::new(clock);
+        self
+    }
+
+    /// Set the latency applied to requests without an explicit delay
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.timing.lock().unwrap().latency = latency;
+        self
+    }
+
+    /// Set the jitter added to request delays
+    #[cfg(feature = "faults")]
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        self.timing.lock().unwrap().jitter = jitter;
+        self
+    }
+
+    /// Seed the random number generator used for jitter, making delays reproducible
+    #[cfg(feature = "faults")]
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.timing.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Answer requests matching no expectation using the provided function.
+    /// Stubbed requests are not counted as expectations and may be made any number of times
+    pub fn stub<F>(self, f: F) -> Self
+    where
+        F: Fn(&Ctx, &Addr, &Req) -> Result<Resp, E> + Send + Sync + 'static,
+    {
+        self.expectations.lock().unwrap().stub = Some(Arc::new(f));
+        self
+    }
+
+    /// Set expectations on the connector, replacing any existing expectations outside of named sequences
+    pub fn expect<T>(&mut self, transactions: T) -> Self
+    where
+        T: Into<VecDeque<MockTransaction<Addr, Req, Resp, Ctx, E>>>,
+    {
+        let mut e = self.expectations.lock().unwrap();
+        let transactions = transactions.into();
+
+        e.groups.retain(|g| g.name.is_some());
+
+        match e.ordered {
+            true => e.groups.push(Group {
+                name: None,
+                transactions,
+            }),
+            false => e.groups.extend(transactions.into_iter().map(|t| Group {
+                name: None,
+                transactions: vec![t].into(),
+            })),
+        }
+
+        self.clone()
+    }
+
+    /// Add a named sequence of expectations to the connector.
+    /// Expectations within a sequence are met in order, but may be interleaved with other expectations
+    pub fn expect_sequence<T>(&mut self, name: &str, transactions: T) -> Self
+    where
+        T: Into<VecDeque<MockTransaction<Addr, Req, Resp, Ctx, E>>>,
+    {
+        self.expectations.lock().unwrap().groups.push(Group {
+            name: Some(name.to_string()),
+            transactions: transactions.into(),
+        });
+
+        self.clone()
+    }
+
+    /// Return errors produced by the provided function for calls matching no expectation, rather than panicking.
+    /// Mismatched calls are collected and reported by finalise()
+    pub fn with_mismatch_error<F>(self, f: F) -> Self
+    where
+        F: Fn(&Mismatch) -> E + Send + Sync + 'static,
+    {
+        self.expectations.lock().unwrap().on_mismatch = Some(Arc::new(f));
+        self
+    }
+
+    /// Fetch the mismatched calls collected by the connector
+    pub fn mismatches(&self) -> Vec<Mismatch> {
+        self.expectations.lock().unwrap().mismatches.clone()
+    }
+
+    /// Fetch the contexts returned with responses to successful requests, in order of completion.
+    /// Fixed replies return the mocked context, while dynamic replies and stubs return the request context
+    pub fn contexts(&self) -> Vec<Ctx> {
+        self.contexts.lock().unwrap().clone()
+    }
+
+    /// Finalise expectations on the connector, panicking with a report of any mismatched calls
+    /// and outstanding expectations
+    pub fn finalise(&mut self) {
+        let (mismatches, remaining) = {
+            let mut e = self.expectations.lock().unwrap();
+            (std::mem::take(&mut e.mismatches), e.drain())
+        };
+
+        if mismatches.is_empty() && remaining.is_empty() {
+            return;
+        }
+
+        let mut report = String::new();
+        for m in mismatches {
+            report.push_str(&format!("{}\n", m));
+        }
+        if !remaining.is_empty() {
+            report.push_str("not all transactions have been evaluated, remaining:\n");
+            for r in remaining {
+                report.push_str(&format!("-   {}\n", r));
+            }
+        }
+
+        panic!("mock connector expectations not met\n{}", report);
+    }
+}
+
+impl<Addr, Req, Resp, E, Ctx> MockConnector<Addr, Req, Resp, E, Ctx> {
+    // Sleep for the delay of a request
+    fn sleep(&self, delay: Option<Duration>) -> BoxFuture<'static, ()> {
+        let mut t = self.timing.lock().unwrap();
+
+        match t.delay(delay) {
+            d if d == Duration::from_secs(0) => future::ready(()).boxed(),
+            d => t.clock.sleep(d),
+        }
+    }
+
+    // Record a call where recording is enabled
+    fn record<Id>(&self, ctx: &Ctx, id: Id, to: &Addr, msg: Muxed<&Req, &Resp>, result: Result<Option<&Resp>, &E>)
+    where
+        Id: Debug + Send + 'static,
+    {
+        let at = self.timing.lock().unwrap().clock.now();
+
+        if let Some(r) = self.recording.lock().unwrap().as_mut() {
+            r.record(at, ctx, MockId::new(id), to, msg, result);
+        }
+    }
+}
+
+impl<Addr, Req, Resp, E, Ctx> MockConnector<Addr, Req, Resp, E, Ctx>
+where
+    Addr: Clone + Send + 'static,
+    Req: Clone + Send + 'static,
+    Resp: Clone + Send + 'static,
+    E: Clone + Send + 'static,
+    Ctx: Clone + Send + 'static,
+{
+    /// Record all calls made via the connector, timestamped using the connector clock.
+    /// This should be enabled after setting the clock, recorded calls are available via transcript()
+    pub fn with_transcript(self) -> Self {
+        let start = self.timing.lock().unwrap().clock.now();
+        *self.recording.lock().unwrap() = Some(Box::new(Recording::new(start)));
+        self
+    }
+
+    /// Fetch the transcript of recorded calls, empty where recording is not enabled
+    pub fn transcript(&self) -> Transcript<Addr, Req, Resp, Ctx, E> {
+        match self.recording.lock().unwrap().as_ref() {
+            Some(r) => r.transcript(),
+            None => Transcript::default(),
+        }
+    }
+}
+
+impl<Addr, Req, Resp, E, Ctx> Default for MockConnector<Addr, Req, Resp, E, Ctx>
+where
+    Addr: Debug + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<Id, Addr, Req, Resp, E, Ctx> Connector<Id, Addr, Req, Resp, E, Ctx>
+    for MockConnector<Addr, Req, Resp, E, Ctx>
+where
+    Id: PartialEq + Debug + Send + 'static,
+    Addr: Debug + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Clone + PartialEq + Debug + Send + 'static,
+{
+    /// Make a request and return the pre-set response
+    /// This checks the request against the specified expectations
+    async fn request(
+        &mut self, ctx: Ctx, id: Id, addr: Addr, req: Req,
+    ) -> Result<Resp, E> {
+        let matches = |t: &MockTransaction<Addr, Req, Resp, Ctx, E>| match t {
+            Muxed::Request(r) => {
+                r.to.matches(&addr)
+                    && r.req.matches(&req)
+                    && r.ctx.as_ref().map(|c| c == &ctx).unwrap_or(true)
+                    && id_matches(&r.id, &id)
+            }
+            _ => false,
+        };
+
+        let transaction = {
+            let mut e = self.expectations.lock().unwrap();
+
+            match (e.stub.clone(), e.on_mismatch.clone()) {
+                (None, None) => Ok(e.take("request", &req, matches)),
+                (Some(stub), _) => e.find(matches).ok_or(Unmatched::Stub(stub)),
+                (None, Some(f)) => match e.find(matches) {
+                    Some(t) => Ok(t),
+                    None => Err(Unmatched::Error(e.mismatch(f, "request", describe("request", &req, &addr, &id, &ctx)))),
+                },
+            }
+        };
+        let res = match transaction {
+            Ok(t) => {
+                let request = t.req().expect("expected request");
+
+                assert!(request.to.matches(&addr), "destination mismatch (expected: {:?}, actual: {:?})", request.to, addr);
+                assert!(request.req.matches(&req), "request mismatch (expected: {:?}, actual: {:?})", request.req, req);
+                if let Some(c) = &request.ctx {
+                    assert_eq!(c, &ctx, "context mismatch");
+                }
+                assert!(id_matches(&request.id, &id), "request ID mismatch (expected: {:?}, actual: {:?})", request.id, id);
+
+                self.sleep(request.delay).await;
+
+                request.resp.resolve(&ctx, &addr, &req).map(|(resp, ctx)| {
+                    self.contexts.lock().unwrap().push(ctx);
+                    resp
+                })
+            }
+            Err(Unmatched::Stub(stub)) => {
+                self.sleep(None).await;
+                stub(&ctx, &addr, &req).inspect(|_| self.contexts.lock().unwrap().push(ctx.clone()))
+            }
+            Err(Unmatched::Error(e)) => Err(e),
+        };
+
+        self.record(&ctx, id, &addr, Muxed::Request(&req), res.as_ref().map(Some));
+
+        res
+    }
+
+    /// Make a response
+    /// This checks the response against provided expectations
+    async fn respond(
+        &mut self, ctx: Ctx, id: Id, addr: Addr, resp: Resp,
+    ) -> Result<(), E> {
+        let matches = |t: &MockTransaction<Addr, Req, Resp, Ctx, E>| match t {
+            Muxed::Response(r) => {
+                r.to.matches(&addr)
+                    && r.resp.matches(&resp)
+                    && r.ctx.as_ref().map(|c| c == &ctx).unwrap_or(true)
+                    && id_matches(&r.id, &id)
+            }
+            _ => false,
+        };
+
+        let transaction = {
+            let mut e = self.expectations.lock().unwrap();
+
+            match e.on_mismatch.clone() {
+                None => Ok(e.take("response", &resp, matches)),
+                Some(f) => e
+                    .find(matches)
+                    .ok_or_else(|| e.mismatch(f, "response", describe("response", &resp, &addr, &id, &ctx))),
+            }
+        };
+
+        let res = transaction.and_then(|t| {
+            let response = t.resp().expect("expected response");
+
+            assert!(response.to.matches(&addr), "destination mismatch (expected: {:?}, actual: {:?})", response.to, addr);
+            assert!(response.resp.matches(&resp), "response mismatch (expected: {:?}, actual: {:?})", response.resp, resp);
+            if let Some(c) = &response.ctx {
+                assert_eq!(c, &ctx, "context mismatch");
+            }
+            assert!(id_matches(&response.id, &id), "request ID mismatch (expected: {:?}, actual: {:?})", response.id, id);
+
+            match response.err {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        });
+
+        self.record(&ctx, id, &addr, Muxed::Response(&resp), res.as_ref().map(|_| None));
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::prelude::*;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    type Mock = MockConnector<u16, u8, u8, (), ()>;
+
+    #[test]
+    fn test_mock_ordered() {
+        let mut m = Mock::new();
+        m.expect(vec![
+            MockTransaction::request(1, 10, Ok((11, ()))),
+            MockTransaction::response(2, 20, None),
+        ]);
+
+        assert_eq!(block_on(m.request((), 0u16, 1, 10)), Ok(11));
+        assert_eq!(block_on(m.respond((), 0u16, 2, 20)), Ok(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "request mismatch")]
+    fn test_mock_ordered_mismatch() {
+        let mut m = Mock::new();
+        m.expect(vec![
+            MockTransaction::request(1, 10, Ok((11, ()))),
+            MockTransaction::request(1, 20, Ok((21, ()))),
+        ]);
+
+        let _ = block_on(m.request((), 0u16, 1, 20));
+    }
+
+    #[test]
+    fn test_mock_unordered() {
+        let mut m = Mock::new().unordered();
+        m.expect(vec![
+            MockTransaction::request(1, 10, Ok((11, ()))),
+            MockTransaction::request(2, 20, Ok((21, ()))),
+            MockTransaction::response(3, 30, None),
+        ]);
+
+        let (mut a, mut b, mut c) = (m.clone(), m.clone(), m.clone());
+
+        assert_eq!(block_on(a.respond((), 0u16, 3, 30)), Ok(()));
+        assert_eq!(block_on(b.request((), 0u16, 2, 20)), Ok(21));
+        assert_eq!(block_on(c.request((), 0u16, 1, 10)), Ok(11));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_mock_sequences() {
+        let mut m = Mock::new();
+        m.expect_sequence(
+            "a",
+            vec![MockTransaction::request(1, 10, Ok((11, ()))), MockTransaction::request(1, 12, Ok((13, ())))],
+        );
+        m.expect_sequence(
+            "b",
+            vec![MockTransaction::request(2, 20, Ok((21, ()))), MockTransaction::request(2, 22, Ok((23, ())))],
+        );
+
+        // Sequences may be interleaved
+        let res = block_on(future::join4(
+            m.clone().request((), 0u16, 2, 20),
+            m.clone().request((), 0u16, 1, 10),
+            m.clone().request((), 0u16, 1, 12),
+            m.clone().request((), 0u16, 2, 22),
+        ));
+        assert_eq!(res, (Ok(21), Ok(11), Ok(13), Ok(23)));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "no matching transaction")]
+    fn test_mock_sequence_order() {
+        let mut m = Mock::new();
+        m.expect_sequence(
+            "a",
+            vec![MockTransaction::request(1, 10, Ok((11, ()))), MockTransaction::request(1, 12, Ok((13, ())))],
+        );
+        m.expect_sequence("b", vec![MockTransaction::request(2, 20, Ok((21, ())))]);
+
+        // Requests within a sequence must be made in order
+        let _ = block_on(m.request((), 0u16, 1, 12));
+    }
+
+    #[derive(Debug)]
+    struct Ping {
+        nonce: u64,
+    }
+
+    #[test]
+    fn test_mock_matchers() {
+        // Requests without PartialEq may be matched using predicates
+        let mut m = MockConnector::<u16, Ping, u8, (), ()>::new();
+        m.expect(vec![
+            MockTransaction::request_matching(any(), |p: &Ping| p.nonce > 10, Ok((1, ()))),
+            MockTransaction::request_matching(eq(2), any(), Ok((2, ()))),
+            MockTransaction::response_matching(eq(3), |r: &u8| *r > 2, None),
+        ]);
+
+        assert_eq!(block_on(m.request((), 0u16, 1, Ping { nonce: 12 })), Ok(1));
+        assert_eq!(block_on(m.request((), 0u16, 2, Ping { nonce: 0 })), Ok(2));
+        assert_eq!(block_on(m.respond((), 0u16, 3, 4)), Ok(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_mock_builders() {
+        let mut m = Mock::new();
+
+        // Builders accept values or matchers for addresses, requests and responses
+        let req = MockRequestBuilder::default().to(1).req(10).resp(Ok((11, ()))).ctx(None).delay(None).build().unwrap();
+        assert_eq!(Muxed::Request(req.clone()), MockTransaction::request(1, 10, Ok((11, ()))));
+
+        let matching = MockRequestBuilder::default()
+            .to_matching(any())
+            .req_matching(Matcher::from(|r: &u8| *r > 10))
+            .resp_with(|_ctx, _to, req| Ok(req + 1))
+            .ctx(None)
+            .delay(None)
+            .build()
+            .unwrap();
+
+        let resp = MockResponseBuilder::default().to(3).resp_matching(any()).err(None).ctx(None).build().unwrap();
+
+        m.expect(vec![Muxed::Request(req), Muxed::Request(matching), Muxed::Response(resp)]);
+
+        assert_eq!(block_on(m.request((), 0u16, 1, 10)), Ok(11));
+        assert_eq!(block_on(m.request((), 0u16, 2, 20)), Ok(21));
+        assert_eq!(block_on(m.respond((), 0u16, 3, 30)), Ok(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "request mismatch")]
+    fn test_mock_matcher_mismatch() {
+        let mut m = MockConnector::<u16, Ping, u8, (), ()>::new();
+        m.expect(vec![MockTransaction::request_matching(any(), |p: &Ping| p.nonce > 10, Ok((1, ())))]);
+
+        let _ = block_on(m.request((), 0u16, 1, Ping { nonce: 2 }));
+    }
+
+    #[test]
+    fn test_mock_dynamic() {
+        let mut m = MockConnector::<u16, Ping, u64, (), ()>::new();
+        m.expect(vec![MockTransaction::request_with(eq(1), any(), |_ctx: &(), _to: &u16, p: &Ping| Ok(p.nonce))]);
+
+        // Responses may echo fields of the actual request
+        assert_eq!(block_on(m.request((), 0u16, 1, Ping { nonce: 0x1234 })), Ok(0x1234));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_mock_stub() {
+        let mut m = Mock::new().stub(|_ctx, to, req| match to {
+            0 => Err(()),
+            _ => Ok(req + 1),
+        });
+        m.expect(vec![MockTransaction::request(1, 10, Ok((100, ())))]);
+
+        // Expectations take priority over the stub, which answers any other request
+        assert_eq!(block_on(m.request((), 0u16, 2, 20)), Ok(21));
+        assert_eq!(block_on(m.request((), 0u16, 1, 10)), Ok(100));
+        assert_eq!(block_on(m.request((), 0u16, 1, 10)), Ok(11));
+        assert_eq!(block_on(m.request((), 0u16, 0, 10)), Err(()));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_mock_delay() {
+        let clock = VirtualClock::new();
+        let mut m = Mock::new().with_clock(clock.clone()).with_latency(Duration::from_secs(1));
+        m.expect(vec![
+            Muxed::Request(MockRequest::new(1, 10, Ok((11, ()))).with_delay(Duration::from_secs(5))),
+            MockTransaction::request(2, 20, Ok((21, ()))),
+        ]);
+
+        block_on(async {
+            // Explicit delays override the connector latency
+            let mut a = m.request((), 0u16, 1, 10);
+            assert!(futures::poll!(&mut a).is_pending());
+            clock.advance(Duration::from_secs(4));
+            assert!(futures::poll!(&mut a).is_pending());
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(a.await, Ok(11));
+
+            let mut b = m.request((), 0u16, 2, 20);
+            assert!(futures::poll!(&mut b).is_pending());
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(b.await, Ok(21));
+        });
+
+        m.finalise();
+    }
+
+    #[cfg(feature = "faults")]
+    #[test]
+    fn test_mock_jitter() {
+        let clock = VirtualClock::new();
+        let m = Mock::new()
+            .with_clock(clock.clone())
+            .with_jitter(Jitter::Uniform(Duration::from_secs(10)))
+            .with_seed(1)
+            .stub(|_, _, req| Ok(*req));
+
+        block_on(async {
+            let mut c = m.clone();
+            let mut a = c.request((), 0u16, 1, 10);
+            assert!(futures::poll!(&mut a).is_pending());
+
+            // Jittered requests complete within the maximum jitter
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(a.await, Ok(10));
+        });
+    }
+
+    #[test]
+    fn test_mock_ids_contexts() {
+        let mut m = MockConnector::<u16, u8, u8, (), u32>::new().unordered();
+        m.expect(vec![
+            Muxed::Request(MockRequest::new(1, 10, Ok((11, 0xaa))).with_id(2u16)),
+            Muxed::Request(MockRequest::new(1, 10, Ok((12, 0xbb))).with_id(1u16)),
+            Muxed::Response(MockResponse::new(1, 20, None).with_id(3u16)),
+        ]);
+
+        // Identical requests are distinguished by ID, with the mocked context recorded
+        assert_eq!(block_on(m.request(0, 1u16, 1, 10)), Ok(12));
+        assert_eq!(block_on(m.request(0, 2u16, 1, 10)), Ok(11));
+        assert_eq!(block_on(m.respond(0, 3u16, 1, 20)), Ok(()));
+        assert_eq!(m.contexts(), vec![0xbb, 0xaa]);
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_mock_contexts_dynamic() {
+        let mut m = MockConnector::<u16, u8, u8, (), u32>::new().stub(|_ctx, to, req| match to {
+            0 => Err(()),
+            _ => Ok(*req),
+        });
+        m.expect(vec![MockTransaction::request_with(any(), any(), |_ctx: &u32, _to: &u16, req: &u8| Ok(*req))]);
+
+        // Dynamic replies and stubs return the request context, with failed requests not recorded
+        assert_eq!(block_on(m.request(0xcc, 0u16, 1, 10)), Ok(10));
+        assert_eq!(block_on(m.request(0xdd, 0u16, 1, 20)), Ok(20));
+        assert_eq!(block_on(m.request(0xee, 0u16, 0, 30)), Err(()));
+        assert_eq!(m.contexts(), vec![0xcc, 0xdd]);
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "request ID mismatch")]
+    fn test_mock_id_mismatch() {
+        let mut m = Mock::new();
+        m.expect(vec![Muxed::Request(MockRequest::new(1, 10, Ok((11, ()))).with_id(1u16))]);
+
+        // IDs of a different type never match
+        let _ = block_on(m.request((), 1u32, 1, 10));
+    }
+
+    #[test]
+    fn test_mock_mismatch_error() {
+        let mut m = MockConnector::<u16, u8, u8, String, ()>::new().with_mismatch_error(|m| m.actual.clone());
+        m.expect(vec![MockTransaction::request(1, 10, Ok((11, ()))), MockTransaction::response(2, 20, None)]);
+
+        // Mismatched calls return errors without consuming expectations
+        assert_eq!(block_on(m.request((), 0u16, 1, 12)), Err("request 12 to 1 (id: 0, ctx: ())".to_string()));
+        assert_eq!(block_on(m.request((), 0u16, 1, 10)), Ok(11));
+        assert_eq!(block_on(m.respond((), 0u16, 2, 20)), Ok(()));
+        assert!(block_on(m.respond((), 0u16, 2, 20)).is_err());
+
+        let mismatches = m.mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].expected.len(), 1);
+        assert!(mismatches[1].expected.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "+   request 12 to 1 (id: 0, ctx: ())")]
+    fn test_mock_mismatch_report() {
+        let mut m = Mock::new().with_mismatch_error(|_| ());
+        m.expect(vec![MockTransaction::request(1, 10, Ok((11, ())))]);
+
+        let _ = block_on(m.request((), 0u16, 1, 12));
+
+        m.finalise();
+    }
+
+    #[test]
+    #[should_panic(expected = "not all transactions have been evaluated")]
+    fn test_mock_finalise() {
+        let mut m = Mock::new().unordered();
+        m.expect(vec![MockTransaction::request(1, 10, Ok((11, ())))]);
+
+        m.finalise();
+    }
+}
@@ -0,0 +1,334 @@
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use super::{MockId, MockRequest, MockResponse, MockTransaction};
+use crate::clock::{Clock, SystemClock};
+use crate::connector::Connector;
+use crate::muxed::Muxed;
+
+/// Call is a recorded request or response made via a connector
+#[derive(Debug, Clone)]
+pub struct Call<Addr, Req, Resp, Ctx, E> {
+    /// Time of the call relative to the start of recording
+    pub at: Duration,
+    pub ctx: Ctx,
+    pub id: MockId,
+    pub to: Addr,
+    /// Request or response sent
+    pub msg: Muxed<Req, Resp>,
+    /// Response received for requests, None for responses, or the error returned
+    pub result: Result<Option<Resp>, E>,
+}
+
+/// Transcript is an ordered record of calls made via a connector
+#[derive(Debug, Clone)]
+pub struct Transcript<Addr, Req, Resp, Ctx, E> {
+    calls: Vec<Call<Addr, Req, Resp, Ctx, E>>,
+}
+
+impl<Addr, Req, Resp, Ctx, E> Transcript<Addr, Req, Resp, Ctx, E> {
+    /// Fetch recorded calls in order
+    pub fn calls(&self) -> &[Call<Addr, Req, Resp, Ctx, E>] {
+        &self.calls
+    }
+
+    /// Convert the transcript into expectations replaying the recorded calls,
+    /// for use with MockConnector::expect
+    pub fn expectations(&self) -> Vec<MockTransaction<Addr, Req, Resp, Ctx, E>>
+    where
+        Addr: PartialEq + Clone,
+        Req: PartialEq + Clone,
+        Resp: PartialEq + Clone,
+        Ctx: Clone,
+        E: Clone,
+    {
+        self.calls
+            .iter()
+            .map(|c| match (&c.msg, &c.result) {
+                (Muxed::Request(req), res) => {
+                    let resp = match res {
+                        Ok(Some(r)) => Ok((r.clone(), c.ctx.clone())),
+                        Ok(None) => unreachable!("requests always record a response"),
+                        Err(e) => Err(e.clone()),
+                    };
+                    let mut r = MockRequest::new(c.to.clone(), req.clone(), resp);
+                    r.id = Some(c.id.clone());
+                    Muxed::Request(r)
+                }
+                (Muxed::Response(resp), res) => {
+                    let mut r = MockResponse::new(c.to.clone(), resp.clone(), res.clone().err());
+                    r.id = Some(c.id.clone());
+                    Muxed::Response(r)
+                }
+            })
+            .collect()
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> Default for Transcript<Addr, Req, Resp, Ctx, E> {
+    fn default() -> Self {
+        Transcript { calls: vec![] }
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> fmt::Display for Transcript<Addr, Req, Resp, Ctx, E>
+where
+    Addr: Debug,
+    Req: Debug,
+    Resp: Debug,
+    Ctx: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in &self.calls {
+            match &c.msg {
+                Muxed::Request(req) => write!(f, "[{:?}] request {:?}", c.at, req)?,
+                Muxed::Response(resp) => write!(f, "[{:?}] response {:?}", c.at, resp)?,
+            }
+            write!(f, " to {:?} (id: {:?}, ctx: {:?})", c.to, c.id, c.ctx)?;
+
+            match &c.result {
+                Ok(Some(resp)) => writeln!(f, " -> {:?}", resp)?,
+                Ok(None) => writeln!(f, " -> ok")?,
+                Err(e) => writeln!(f, " -> error {:?}", e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record is the type erased interface to a call recording, allowing MockConnector to hold a recording
+/// without additional bounds on its type parameters
+pub(crate) trait Record<Addr, Req, Resp, Ctx, E>: Send {
+    /// Record a call
+    fn record(
+        &mut self, at: Instant, ctx: &Ctx, id: MockId, to: &Addr, msg: Muxed<&Req, &Resp>,
+        result: Result<Option<&Resp>, &E>,
+    );
+
+    /// Fetch the transcript of recorded calls
+    fn transcript(&self) -> Transcript<Addr, Req, Resp, Ctx, E>;
+}
+
+/// Recording holds calls recorded since a start time
+pub(crate) struct Recording<Addr, Req, Resp, Ctx, E> {
+    start: Instant,
+    calls: Vec<Call<Addr, Req, Resp, Ctx, E>>,
+}
+
+impl<Addr, Req, Resp, Ctx, E> Recording<Addr, Req, Resp, Ctx, E> {
+    pub(crate) fn new(start: Instant) -> Self {
+        Recording { start, calls: vec![] }
+    }
+}
+
+impl<Addr, Req, Resp, Ctx, E> Record<Addr, Req, Resp, Ctx, E> for Recording<Addr, Req, Resp, Ctx, E>
+where
+    Addr: Clone + Send,
+    Req: Clone + Send,
+    Resp: Clone + Send,
+    Ctx: Clone + Send,
+    E: Clone + Send,
+{
+    fn record(
+        &mut self, at: Instant, ctx: &Ctx, id: MockId, to: &Addr, msg: Muxed<&Req, &Resp>,
+        result: Result<Option<&Resp>, &E>,
+    ) {
+        self.calls.push(Call {
+            at: at.saturating_duration_since(self.start),
+            ctx: ctx.clone(),
+            id,
+            to: to.clone(),
+            msg: match msg {
+                Muxed::Request(r) => Muxed::Request(r.clone()),
+                Muxed::Response(r) => Muxed::Response(r.clone()),
+            },
+            result: result.map(|r| r.cloned()).map_err(|e| e.clone()),
+        });
+    }
+
+    fn transcript(&self) -> Transcript<Addr, Req, Resp, Ctx, E> {
+        Transcript {
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+type SharedRecording<Addr, Req, Resp, Ctx, E> = Arc<Mutex<Recording<Addr, Req, Resp, Ctx, E>>>;
+
+/// Recorder wraps a connector, recording calls made via it so that real sessions may be
+/// replayed as MockConnector expectations
+pub struct Recorder<Id, Addr, Req, Resp, E, Ctx, Conn> {
+    conn: Conn,
+    clock: Arc<dyn Clock>,
+    recording: SharedRecording<Addr, Req, Resp, Ctx, E>,
+
+    _id: PhantomData<Id>,
+}
+
+impl<Id, Addr, Req, Resp, E, Ctx, Conn> Clone for Recorder<Id, Addr, Req, Resp, E, Ctx, Conn>
+where
+    Conn: Clone,
+{
+    fn clone(&self) -> Self {
+        Recorder {
+            conn: self.conn.clone(),
+            clock: self.clock.clone(),
+            recording: self.recording.clone(),
+            _id: PhantomData,
+        }
+    }
+}
+
+impl<Id, Addr, Req, Resp, E, Ctx, Conn> Unpin for Recorder<Id, Addr, Req, Resp, E, Ctx, Conn> where Conn: Unpin {}
+
+impl<Id, Addr, Req, Resp, E, Ctx, Conn> Recorder<Id, Addr, Req, Resp, E, Ctx, Conn>
+where
+    Addr: Clone + Send,
+    Req: Clone + Send,
+    Resp: Clone + Send,
+    Ctx: Clone + Send,
+    E: Clone + Send,
+{
+    /// Create a new recorder over the provided connector, timestamping calls using the system clock
+    pub fn new(conn: Conn) -> Self {
+        Self::with_clock(conn, SystemClock)
+    }
+
+    /// Create a new recorder over the provided connector, timestamping calls using the provided clock
+    pub fn with_clock<C: Clock + 'static>(conn: Conn, clock: C) -> Self {
+        Recorder {
+            conn,
+            recording: Arc::new(Mutex::new(Recording::new(clock.now()))),
+            clock: Arc::new(clock),
+            _id: PhantomData,
+        }
+    }
+
+    /// Fetch the transcript of calls recorded by this and any cloned recorders
+    pub fn transcript(&self) -> Transcript<Addr, Req, Resp, Ctx, E> {
+        self.recording.lock().unwrap().transcript()
+    }
+}
+
+#[async_trait]
+impl<Id, Addr, Req, Resp, E, Ctx, Conn> Connector<Id, Addr, Req, Resp, E, Ctx>
+    for Recorder<Id, Addr, Req, Resp, E, Ctx, Conn>
+where
+    Id: Debug + Clone + Send + 'static,
+    Addr: Clone + Send + 'static,
+    Req: Clone + Send + 'static,
+    Resp: Clone + Send + 'static,
+    E: Clone + Send + 'static,
+    Ctx: Clone + Send + 'static,
+    Conn: Connector<Id, Addr, Req, Resp, E, Ctx> + Send + 'static,
+{
+    /// Send a request via the underlying connector, recording the request and result
+    async fn request(&mut self, ctx: Ctx, id: Id, to: Addr, req: Req) -> Result<Resp, E> {
+        let (c, i, t, r) = (ctx.clone(), id.clone(), to.clone(), req.clone());
+
+        let res = self.conn.request(ctx, id, to, req).await;
+
+        let at = self.clock.now();
+        self.recording.lock().unwrap().record(at, &c, MockId::new(i), &t, Muxed::Request(&r), res.as_ref().map(Some));
+
+        res
+    }
+
+    /// Send a response via the underlying connector, recording the response and result
+    async fn respond(&mut self, ctx: Ctx, id: Id, to: Addr, resp: Resp) -> Result<(), E> {
+        let (c, i, t, r) = (ctx.clone(), id.clone(), to.clone(), resp.clone());
+
+        let res = self.conn.respond(ctx, id, to, resp).await;
+
+        let at = self.clock.now();
+        self.recording.lock().unwrap().record(at, &c, MockId::new(i), &t, Muxed::Response(&r), res.as_ref().map(|_| None));
+
+        res
+    }
+}
+
+/// Incoming messages are passed through from the underlying connector
+impl<Id, Addr, Req, Resp, E, Ctx, Conn> Stream for Recorder<Id, Addr, Req, Resp, E, Ctx, Conn>
+where
+    Conn: Stream + Unpin,
+{
+    type Item = Conn::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().conn.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::mock::MockConnector;
+
+    type Mock = MockConnector<u16, u8, u8, (), ()>;
+
+    #[test]
+    fn test_mock_transcript() {
+        let clock = VirtualClock::new();
+        let mut m = Mock::new().with_clock(clock.clone()).with_transcript();
+        m.expect(vec![MockTransaction::request(1, 10, Ok((11, ()))), MockTransaction::response(2, 20, Some(()))]);
+
+        assert_eq!(block_on(m.request((), 1u32, 1, 10)), Ok(11));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(block_on(m.respond((), 2u32, 2, 20)), Err(()));
+
+        let t = m.transcript();
+        let calls = t.calls();
+        assert_eq!(calls.len(), 2);
+
+        assert_eq!(calls[0].at, Duration::from_secs(0));
+        assert!(calls[0].id.matches(&1u32));
+        assert_eq!(calls[0].msg, Muxed::Request(10));
+        assert_eq!(calls[0].result, Ok(Some(11)));
+
+        assert_eq!(calls[1].at, Duration::from_secs(1));
+        assert_eq!(calls[1].to, 2);
+        assert_eq!(calls[1].result, Err(()));
+
+        assert_eq!(
+            t.to_string(),
+            "[0ns] request 10 to 1 (id: 1, ctx: ()) -> 11\n[1s] response 20 to 2 (id: 2, ctx: ()) -> error ()\n"
+        );
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_recorder_replay() {
+        // Record a session against a stand in for a real connector
+        let real = Mock::new().stub(|_, to, req| match to {
+            0 => Err(()),
+            _ => Ok(req * 2),
+        });
+        let mut r = Recorder::new(real);
+
+        assert_eq!(block_on(r.request((), 1u16, 1, 10)), Ok(20));
+        assert_eq!(block_on(r.request((), 2u16, 0, 12)), Err(()));
+
+        // Then replay it as expectations
+        let mut m = Mock::new();
+        m.expect(r.transcript().expectations());
+
+        assert_eq!(block_on(m.request((), 1u16, 1, 10)), Ok(20));
+        assert_eq!(block_on(m.request((), 2u16, 0, 12)), Err(()));
+
+        m.finalise();
+    }
+}
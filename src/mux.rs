@@ -12,8 +12,22 @@ use futures::task::{Context, Poll};
 use async_trait::async_trait;
 
 use crate::connector::Connector;
+use crate::dedup::{Dedup, Filter, Seen, Verdict};
 use crate::muxed::Muxed;
 
+type Message<ReqId, Target, Req, Resp, Ctx> = (ReqId, Target, Muxed<Req, Resp>, Ctx);
+type Receiver<ReqId, Target, Req, Resp, Ctx> = Arc<Mutex<ChannelReceiver<Message<ReqId, Target, Req, Resp, Ctx>>>>;
+
+type DedupFilter<ReqId, Target, Resp, Ctx> = Arc<Mutex<Option<Box<dyn Filter<ReqId, Target, Resp, Ctx>>>>>;
+
+type Rejected<ReqId, E> = Arc<dyn Fn(&ReqId) -> E + Send + Sync>;
+
+/// Pending holds outstanding requests, and the error source once the mux has been closed
+struct Pending<ReqId, Resp, E> {
+    requests: HashMap<ReqId, OneshotSender<Result<Resp, E>>>,
+    closed: Option<Arc<dyn Fn() -> E + Send + Sync>>,
+}
+
 /// Mux is a futures based request response multiplexer.
 /// This provides a Source interface to drain messages sent, and receives messages via the handle() method,
 /// allowing responses to be consumed and requests forwarded on.
@@ -23,10 +37,12 @@ use crate::muxed::Muxed;
 /// Req and Resp are the request and response messages
 /// Ctx is a a shared context
 pub struct Mux<ReqId, Target, Req, Resp, E, Ctx> {
-    requests: Arc<Mutex<HashMap<ReqId, Box<OneshotSender<Resp>>>>>,
+    pending: Arc<Mutex<Pending<ReqId, Resp, E>>>,
+    rejected: Rejected<ReqId, E>,
+    dedup: DedupFilter<ReqId, Target, Resp, Ctx>,
 
-    sender: ChannelSender<(ReqId, Target, Muxed<Req, Resp>, Ctx)>,
-    receiver: Arc<Mutex<ChannelReceiver<(ReqId, Target, Muxed<Req, Resp>, Ctx)>>>,
+    sender: ChannelSender<Message<ReqId, Target, Req, Resp, Ctx>>,
+    receiver: Receiver<ReqId, Target, Req, Resp, Ctx>,
 
     _addr: PhantomData<Target>,
     _req: PhantomData<Req>,
@@ -45,7 +61,9 @@ where
 {
     fn clone(&self) -> Self {
         Mux {
-            requests: self.requests.clone(),
+            pending: self.pending.clone(),
+            rejected: self.rejected.clone(),
+            dedup: self.dedup.clone(),
             sender: self.sender.clone(),
             receiver: self.receiver.clone(),
             _ctx: PhantomData,
@@ -65,12 +83,20 @@ where
     E: Debug + Send + 'static,
     Ctx: Debug + Clone + Send + 'static,
 {
-    /// Create a new mux over the provided sender
-    pub fn new() -> Mux<ReqId, Target, Req, Resp, E, Ctx> {
+    /// Create a new mux, failing requests that reuse the ID of a pending request with errors from the provided function
+    pub fn new<F>(rejected: F) -> Mux<ReqId, Target, Req, Resp, E, Ctx>
+    where
+        F: Fn(&ReqId) -> E + Send + Sync + 'static,
+    {
         let (tx, rx) = channel(0);
 
         Mux {
-            requests: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new(Mutex::new(Pending {
+                requests: HashMap::new(),
+                closed: None,
+            })),
+            rejected: Arc::new(rejected),
+            dedup: Arc::new(Mutex::new(None)),
             sender: tx,
             receiver: Arc::new(Mutex::new(rx)),
             _ctx: PhantomData,
@@ -85,8 +111,21 @@ where
     pub fn handle(
         &mut self, id: ReqId, addr: Target, message: Muxed<Req, Resp>) -> Result<Option<(Target, Req)>, E> {
         let r = match message {
-            // Requests get passed through the mux
-            Muxed::Request(req) => Some((addr, req)),
+            // Requests get passed through the mux, unless filtered as duplicates
+            Muxed::Request(req) => match self.check_dedup(&id, &addr) {
+                Verdict::Fresh => Some((addr, req)),
+                Verdict::Drop => {
+                    debug!("Request id: '{:?}' from {:?}, dropping duplicate", id, addr);
+                    None
+                }
+                Verdict::Respond(resp, ctx) => {
+                    debug!("Request id: '{:?}' from {:?}, resending cached response", id, addr);
+                    if let Err(e) = self.sender.clone().try_send((id, addr, Muxed::Response(resp), ctx)) {
+                        warn!("Failed to resend cached response: {:?}", e);
+                    }
+                    None
+                }
+            },
             // Responses get matched with outstanding requests
             Muxed::Response(resp) => {
                 self.handle_resp(id, addr, resp)?;
@@ -97,16 +136,107 @@ where
         Ok(r)
     }
 
+    /// Send a request without registering it as pending, for requests that expect no response
+    pub async fn notify(&mut self, ctx: Ctx, id: ReqId, addr: Target, req: Req) -> Result<(), E> {
+        if let Some(e) = self.closed_err() {
+            return Err(e);
+        }
+
+        let mut sender = self.sender.clone();
+        match sender.send((id.clone(), addr, Muxed::Request(req), ctx)).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(self.lost_err(&id)),
+        }
+    }
+
     /// Handle a pre-decoded response message
     pub fn handle_resp(&mut self, id: ReqId, _target: Target, resp: Resp) -> Result<(), E> {
-        let ch = { self.requests.lock().unwrap().remove(&id) };
+        let ch = { self.pending.lock().unwrap().requests.remove(&id) };
         if let Some(ch) = ch {
-            ch.send(resp).unwrap();
+            if ch.send(Ok(resp)).is_err() {
+                info!("Response id: '{:?}', request dropped", id);
+            }
         } else {
             info!("Response id: '{:?}', no request pending", id);
         }
         Ok(())
     }
+
+    /// Fetch the number of requests awaiting responses
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().requests.len()
+    }
+
+    /// Cancel a pending request, failing it with the provided error and returning true if the request was pending.
+    /// This is used to release requests that have timed out or whose future has been dropped
+    pub fn cancel(&self, id: &ReqId, err: E) -> bool {
+        match self.pending.lock().unwrap().requests.remove(id) {
+            Some(ch) => {
+                let _ = ch.send(Err(err));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close the mux, failing pending and subsequent requests and responses with errors from the provided function.
+    /// This is used by transports to propagate connection loss to callers
+    pub fn close<F>(&self, err: F)
+    where
+        F: Fn() -> E + Send + Sync + 'static,
+    {
+        let mut pending = self.pending.lock().unwrap();
+
+        for (id, ch) in pending.requests.drain() {
+            debug!("Request id: '{:?}' failed, mux closed", id);
+            let _ = ch.send(Err(err()));
+        }
+
+        pending.closed = Some(Arc::new(err));
+    }
+
+    /// Check whether the mux has been closed
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().closed.is_some()
+    }
+
+    fn check_dedup(&self, id: &ReqId, target: &Target) -> Verdict<Resp, Ctx> {
+        match self.dedup.lock().unwrap().as_mut() {
+            Some(f) => f.check(id, target),
+            None => Verdict::Fresh,
+        }
+    }
+
+    fn closed_err(&self) -> Option<E> {
+        self.pending.lock().unwrap().closed.as_ref().map(|f| f())
+    }
+
+    // Fetch the error for a request dropped without a response.
+    // Pending requests are always failed with an error before being dropped, so this only occurs
+    // once the mux has been closed, with the rejection error as a fallback
+    fn lost_err(&self, id: &ReqId) -> E {
+        match self.closed_err() {
+            Some(e) => e,
+            None => (self.rejected)(id),
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx> Mux<ReqId, Target, Req, Resp, E, Ctx>
+where
+    ReqId: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + Clone + Send + 'static,
+    Target: std::cmp::Eq + std::hash::Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Clone + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Enable filtering of duplicated or replayed incoming requests in handle(), using the provided window.
+    /// This applies to all clones of the mux
+    pub fn with_dedup(self, dedup: Dedup) -> Self {
+        *self.dedup.lock().unwrap() = Some(Box::new(Seen::new(dedup)));
+        self
+    }
 }
 
 #[async_trait]
@@ -127,43 +257,59 @@ where
         // Create future channel
         let (tx, rx) = oneshot::channel();
 
-        // Save response to map
-        { self.requests
-            .lock()
-            .unwrap()
-            .insert(id.clone(), Box::new(tx)) };
+        // Save response to map, failing if the mux has been closed or the ID is already pending
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(f) = &pending.closed {
+                return Err(f());
+            }
+            if pending.requests.contains_key(&id) {
+                debug!("Request id: '{:?}' rejected, already pending", id);
+                return Err((self.rejected)(&id));
+            }
+            pending.requests.insert(id.clone(), tx);
+        }
 
-        // Send request and return channel future
+        // Send request, returning early where the request is failed before it is sent
         let mut sender = self.sender.clone();
+        let send = sender.send((id.clone(), addr, Muxed::Request(req), ctx));
 
-        match sender.send((id, addr, Muxed::Request(req), ctx)).await {
-            Ok(_) => (),
-            Err(e) => panic!(e),
-        };
-
-        let res = match rx.await {
-            Ok(r) => r,
-            Err(e) => panic!(e),
+        let rx = match future::select(send, rx).await {
+            future::Either::Left((Ok(_), rx)) => rx,
+            future::Either::Left((Err(_), _)) => return Err(self.lost_err(&id)),
+            future::Either::Right((res, _)) => return res.unwrap_or_else(|_| Err(self.lost_err(&id))),
         };
 
-        Ok(res)
+        // Await response
+        rx.await.unwrap_or_else(|_| Err(self.lost_err(&id)))
     }
 
     async fn respond(
         &mut self, ctx: Ctx, id: ReqId, addr: Target, resp: Resp,
     ) -> Result<(), E> {
+        if let Some(e) = self.closed_err() {
+            return Err(e);
+        }
+
+        if let Some(f) = self.dedup.lock().unwrap().as_mut() {
+            f.record(&id, &addr, &resp, &ctx);
+        }
+
         // Send request and return channel future
         let mut sender = self.sender.clone();
 
         match sender.send((id, addr, Muxed::Response(resp), ctx)).await {
             Ok(_) => (),
-            Err(e) => panic!(e),
+            Err(e) => panic!("{:?}", e),
         };
 
         Ok(())
     }
 }
 
+// Mux holds no pinned state, so may be polled regardless of type parameters
+impl<ReqId, Target, Req, Resp, E, Ctx> Unpin for Mux<ReqId, Target, Req, Resp, E, Ctx> {}
+
 // Stream implementation to allow polling from mux
 impl<ReqId, Target, Req, Resp, E, Ctx> Stream for Mux<ReqId, Target, Req, Resp, E, Ctx> {
     type Item = (ReqId, Target, Muxed<Req, Resp>, Ctx);
@@ -192,7 +338,7 @@ mod tests {
 
     #[test]
     fn test_mux() {
-        let mut mux: Mux<u16, u32, A, B, (), C> = Mux::new();
+        let mut mux: Mux<u16, u32, A, B, (), C> = Mux::new(|_| ());
 
         let req_id = 10;
         let addr = 12;
@@ -200,7 +346,6 @@ mod tests {
         let resp = B(30);
 
         let ctx_out = C(40);
-        let ctx_in = C(50);
 
         // Make a request and check the response
         let mut m = mux.clone();
@@ -217,8 +362,6 @@ mod tests {
                 assert_eq!(m.req(), Some(req));
                 assert_eq!(c, ctx_out);
     
-                let resp = resp.clone();
-                
                 mux.handle_resp(req_id, addr, resp).unwrap();
             }
         }.boxed();
@@ -228,4 +371,70 @@ mod tests {
         let _ = block_on(future::select(a, b));
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mux_close() {
+        let mut mux: Mux<u16, u32, A, B, &'static str, C> = Mux::new(|_| "duplicate");
+
+        // Make a request and close the mux once it has been sent
+        let mut m = mux.clone();
+        let a = async {
+            let r = m.request(C(0), 1, 2, A(3)).await;
+            assert_eq!(r, Err("closed"));
+        }.boxed();
+
+        let b = async {
+            while let Some((i, _a, _m, _c)) = mux.next().await {
+                assert_eq!(i, 1);
+                assert_eq!(mux.pending(), 1);
+                mux.close(|| "closed");
+            }
+        }.boxed();
+
+        let _ = block_on(future::select(a, b));
+
+        // Subsequent requests and responses fail immediately
+        assert!(mux.is_closed());
+        assert_eq!(mux.pending(), 0);
+        assert_eq!(block_on(mux.request(C(0), 4, 2, A(5))), Err("closed"));
+        assert_eq!(block_on(mux.respond(C(0), 4, 2, B(5))), Err("closed"));
+    }
+
+    #[test]
+    fn test_mux_cancel() {
+        let mux: Mux<u16, u32, A, B, &'static str, C> = Mux::new(|_| "duplicate");
+
+        block_on(async {
+            let mut m = mux.clone();
+            let mut req = m.request(C(0), 1, 2, A(3));
+
+            // Requests are failed when cancelled or closed, including those not yet sent
+            assert!(futures::poll!(&mut req).is_pending());
+            assert!(mux.cancel(&1, "cancelled"));
+            assert_eq!(req.await, Err("cancelled"));
+            assert!(!mux.cancel(&1, "cancelled"));
+
+            let mut req = m.request(C(0), 2, 2, A(3));
+            assert!(futures::poll!(&mut req).is_pending());
+            mux.close(|| "closed");
+            assert_eq!(req.await, Err("closed"));
+        });
+    }
+    #[test]
+    fn test_mux_duplicate() {
+        let mux: Mux<u16, u32, A, B, &'static str, C> = Mux::new(|_| "duplicate");
+
+        block_on(async {
+            let mut m = mux.clone();
+            let mut req = m.request(C(0), 1, 2, A(3));
+            assert!(futures::poll!(&mut req).is_pending());
+
+            // Requests reusing a pending ID are rejected, leaving the original pending
+            assert_eq!(mux.clone().request(C(0), 1, 2, A(4)).await, Err("duplicate"));
+            assert_eq!(mux.pending(), 1);
+
+            mux.clone().handle_resp(1, 2, B(5)).unwrap();
+            assert_eq!(req.await, Ok(B(5)));
+        });
+    }
+}
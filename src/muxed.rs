@@ -1,5 +1,6 @@
 /// Muxed is a container for either a request or response message
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Muxed<Req, Resp> {
     Request(Req),
     Response(Resp),
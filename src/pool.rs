@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::future::{AbortHandle, Abortable, BoxFuture};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use crate::connector::Connector;
+use crate::stream::{Error, Incoming, StreamConnector};
+
+/// Dialed is a newly established connection, the connector and the future driving it
+pub type Dialed<ReqId, Target, Req, Resp, Ctx> =
+    (StreamConnector<ReqId, Target, Req, Resp, Ctx>, BoxFuture<'static, Result<(), Error>>);
+
+/// Dial is a user supplied function to establish a connection to a given target
+pub type Dial<ReqId, Target, Req, Resp, Ctx> =
+    Arc<dyn Fn(Target) -> BoxFuture<'static, Result<Dialed<ReqId, Target, Req, Resp, Ctx>, Error>> + Send + Sync>;
+
+/// Spawn is a user supplied function to spawn connection tasks on an executor
+pub type Spawn = Arc<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>;
+
+struct Entry<ReqId, Target, Req, Resp, Ctx> {
+    index: u64,
+    conn: StreamConnector<ReqId, Target, Req, Resp, Ctx>,
+    abort: AbortHandle,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Entry<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    // Close the connection, failing requests pending on it, then stop the connection task
+    fn close(self) {
+        self.conn.close();
+        self.abort.abort();
+    }
+}
+
+type Entries<ReqId, Target, Req, Resp, Ctx> = Arc<Mutex<HashMap<Target, Entry<ReqId, Target, Req, Resp, Ctx>>>>;
+
+type IncomingReceiver<ReqId, Target, Req, Ctx> = Arc<Mutex<mpsc::Receiver<Incoming<ReqId, Target, Req, Ctx>>>>;
+
+/// MuxPool manages a set of per-target connections, implementing Connector by routing requests
+/// and responses to the connection for each target.
+/// Connections are dialed lazily on request using the provided Dial function, or may be inserted
+/// directly (for example when accepted by a listener), and are removed from the pool when closed.
+/// Incoming requests from all connections are available via the Stream interface.
+pub struct MuxPool<ReqId, Target, Req, Resp, Ctx> {
+    conns: Entries<ReqId, Target, Req, Resp, Ctx>,
+    index: Arc<AtomicU64>,
+
+    dial: Dial<ReqId, Target, Req, Resp, Ctx>,
+    spawn: Spawn,
+
+    incoming_tx: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>,
+    incoming_rx: IncomingReceiver<ReqId, Target, Req, Ctx>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Clone for MuxPool<ReqId, Target, Req, Resp, Ctx> {
+    fn clone(&self) -> Self {
+        MuxPool {
+            conns: self.conns.clone(),
+            index: self.index.clone(),
+            dial: self.dial.clone(),
+            spawn: self.spawn.clone(),
+            incoming_tx: self.incoming_tx.clone(),
+            incoming_rx: self.incoming_rx.clone(),
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> MuxPool<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Create a new pool using the provided dial and spawn functions
+    pub fn new<D, S>(dial: D, spawn: S) -> Self
+    where
+        D: Fn(Target) -> BoxFuture<'static, Result<Dialed<ReqId, Target, Req, Resp, Ctx>, Error>>
+            + Send
+            + Sync
+            + 'static,
+        S: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    {
+        let (tx, rx) = mpsc::channel(0);
+
+        MuxPool {
+            conns: Arc::new(Mutex::new(HashMap::new())),
+            index: Arc::new(AtomicU64::new(0)),
+            dial: Arc::new(dial),
+            spawn: Arc::new(spawn),
+            incoming_tx: tx,
+            incoming_rx: Arc::new(Mutex::new(rx)),
+        }
+    }
+
+    /// Insert an established connection for the provided target, replacing (and closing) any existing connection.
+    /// The driver is spawned and the connection removed from the pool when the driver exits.
+    pub fn insert(
+        &self, target: Target, conn: StreamConnector<ReqId, Target, Req, Resp, Ctx>,
+        driver: BoxFuture<'static, Result<(), Error>>,
+    ) {
+        let index = self.index.fetch_add(1, Ordering::SeqCst);
+        let (abort, registration) = AbortHandle::new_pair();
+
+        let entry = Entry {
+            index,
+            conn: conn.clone(),
+            abort,
+        };
+        if let Some(e) = self.conns.lock().unwrap().insert(target.clone(), entry) {
+            e.close();
+        }
+
+        // Forward incoming requests from the connection to the pool
+        let closing = conn.clone();
+        let mut incoming = conn;
+        let mut tx = self.incoming_tx.clone();
+        let forward = async move {
+            while let Some(i) = incoming.next().await {
+                if tx.send(i).await.is_err() {
+                    break;
+                }
+            }
+            Ok::<(), Error>(())
+        };
+
+        let conns = self.conns.clone();
+        let task = async move {
+            let res = future::select(driver, forward.boxed()).await.factor_first().0;
+            debug!("Connection to {:?} closed: {:?}", target, res);
+
+            // The driver is dropped if forwarding ends first, so close the connection here
+            closing.close();
+
+            let mut conns = conns.lock().unwrap();
+            if conns.get(&target).map(|e| e.index) == Some(index) {
+                conns.remove(&target);
+            }
+        };
+
+        (self.spawn)(Abortable::new(task, registration).map(|_| ()).boxed());
+    }
+
+    /// Fetch the connection for the provided target, dialing a new connection if none exists
+    pub async fn connect(&self, target: Target) -> Result<StreamConnector<ReqId, Target, Req, Resp, Ctx>, Error> {
+        if let Some(c) = self.get(&target) {
+            return Ok(c);
+        }
+
+        let (conn, driver) = (self.dial)(target.clone()).await?;
+
+        // Prefer a connection established concurrently, dropping the newly dialed one
+        if let Some(c) = self.get(&target) {
+            return Ok(c);
+        }
+
+        self.insert(target, conn.clone(), driver);
+
+        Ok(conn)
+    }
+
+    /// Fetch the existing connection for the provided target
+    pub fn get(&self, target: &Target) -> Option<StreamConnector<ReqId, Target, Req, Resp, Ctx>> {
+        self.conns.lock().unwrap().get(target).map(|e| e.conn.clone())
+    }
+
+    /// Remove and close the connection for the provided target
+    pub fn remove(&self, target: &Target) -> bool {
+        match self.conns.lock().unwrap().remove(target) {
+            Some(e) => {
+                e.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// List targets with active connections
+    pub fn targets(&self) -> Vec<Target> {
+        self.conns.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, Ctx> Connector<ReqId, Target, Req, Resp, Error, Ctx>
+    for MuxPool<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Send a request to the target, dialing a new connection if required
+    async fn request(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req,
+    ) -> Result<Resp, Error> {
+        let mut conn = self.connect(target.clone()).await?;
+
+        conn.request(ctx, req_id, target, req).await
+    }
+
+    /// Send a response to the target, this fails if no connection exists
+    async fn respond(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp,
+    ) -> Result<(), Error> {
+        let mut conn = self.get(&target).ok_or(Error::Closed)?;
+
+        conn.respond(ctx, req_id, target, resp).await
+    }
+}
+
+// Stream implementation to allow polling for incoming requests from all connections
+impl<ReqId, Target, Req, Resp, Ctx> Stream for MuxPool<ReqId, Target, Req, Resp, Ctx> {
+    type Item = Incoming<ReqId, Target, Req, Ctx>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.incoming_rx.lock().unwrap().poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::LengthPrefix;
+
+    type Pool = MuxPool<u16, SocketAddr, Vec<u8>, Vec<u8>, ()>;
+
+    fn spawn(f: BoxFuture<'static, ()>) {
+        task::spawn(f);
+    }
+
+    fn pool() -> Pool {
+        pool_with(spawn)
+    }
+
+    // Create a pool signalling as each connection task exits
+    fn pool_notify() -> (Pool, mpsc::UnboundedReceiver<()>) {
+        let (tx, rx) = mpsc::unbounded();
+        let spawn = move |f: BoxFuture<'static, ()>| {
+            let tx = tx.clone();
+            task::spawn(f.map(move |_| tx.unbounded_send(()).unwrap()));
+        };
+
+        (pool_with(spawn), rx)
+    }
+
+    fn pool_with<S>(spawn: S) -> Pool
+    where
+        S: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    {
+        MuxPool::new(
+            |addr: SocketAddr| {
+                async move {
+                    let s = TcpStream::connect(addr).await?;
+                    let (conn, driver) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), addr, ());
+                    Ok((conn, driver.run().boxed()))
+                }
+                .boxed()
+            },
+            spawn,
+        )
+    }
+
+    // Start a server pool, accepting connections and responding to requests with the request length
+    async fn server() -> (SocketAddr, Pool) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let p = pool();
+
+        let server = p.clone();
+        task::spawn(async move {
+            while let Ok((s, peer)) = listener.accept().await {
+                let (conn, driver) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), peer, ());
+                server.insert(peer, conn, driver.run().boxed());
+            }
+        });
+
+        let mut server = p.clone();
+        task::spawn(async move {
+            while let Some((id, from, req, _ctx)) = server.next().await {
+                server.respond((), id, from, vec![req.len() as u8]).await.unwrap();
+            }
+        });
+
+        (addr, p)
+    }
+
+    // Start a server accepting connections without responding, signalling once data is received
+    async fn silent() -> (SocketAddr, mpsc::Receiver<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(16);
+
+        task::spawn(async move {
+            while let Ok((mut s, _)) = listener.accept().await {
+                let mut tx = tx.clone();
+                task::spawn(async move {
+                    let mut buff = [0u8; 64];
+                    while let Ok(n) = s.read(&mut buff).await {
+                        if n == 0 || tx.send(()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        (addr, rx)
+    }
+
+    #[test]
+    fn test_pool_pending() {
+        task::block_on(async {
+            let (addr, mut received) = silent().await;
+            let p = pool();
+
+            // Requests pending on removed connections fail
+            let mut c = p.clone();
+            let req = task::spawn(async move { c.request((), 1, addr, vec![1]).await });
+            received.next().await;
+
+            let mut conn = p.get(&addr).unwrap();
+            assert!(p.remove(&addr));
+            assert!(matches!(req.await, Err(Error::ConnectionLost)));
+
+            // As do later requests via clones of the removed connection
+            assert!(matches!(conn.request((), 2, addr, vec![2]).await, Err(Error::ConnectionLost)));
+
+            // And requests pending on replaced connections
+            let mut c = p.clone();
+            let req = task::spawn(async move { c.request((), 3, addr, vec![3]).await });
+            received.next().await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (conn, driver) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), addr, ());
+            p.insert(addr, conn, driver.run().boxed());
+            assert!(matches!(req.await, Err(Error::ConnectionLost)));
+        });
+    }
+
+    #[test]
+    fn test_pool() {
+        task::block_on(async {
+            let (a, server_a) = server().await;
+            let (b, _server_b) = server().await;
+
+            let (mut p, mut exited) = pool_notify();
+
+            // Requests lazily dial and route to each target
+            assert_eq!(p.request((), 1, a, vec![0; 3]).await.unwrap(), vec![3]);
+            assert_eq!(p.request((), 2, b, vec![0; 5]).await.unwrap(), vec![5]);
+            assert_eq!(p.request((), 3, a, vec![0; 7]).await.unwrap(), vec![7]);
+
+            let mut targets = p.targets();
+            targets.sort();
+            let mut expected = vec![a, b];
+            expected.sort();
+            assert_eq!(targets, expected);
+            assert_eq!(server_a.targets().len(), 1);
+
+            // Connections closed by the remote peer are removed
+            for t in server_a.targets() {
+                server_a.remove(&t);
+            }
+
+            while p.get(&a).is_some() {
+                exited.next().await;
+            }
+            assert_eq!(p.targets(), vec![b]);
+
+            // And redialed on the next request
+            assert_eq!(p.request((), 4, a, vec![0; 2]).await.unwrap(), vec![2]);
+        });
+    }
+}
@@ -0,0 +1,168 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+
+use prost::Message;
+
+use crate::codec::Codec;
+use crate::muxed::Muxed;
+
+/// Protobuf schema for the Envelope message, for use by non-Rust peers
+pub const ENVELOPE_PROTO: &str = include_str!("../proto/envelope.proto");
+
+/// Kind distinguishes requests from responses in a protobuf Envelope
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Kind {
+    Unspecified = 0,
+    Request = 1,
+    Response = 2,
+}
+
+/// Envelope is the protobuf message carrying an encoded request or response,
+/// see `proto/envelope.proto` for the schema
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Envelope {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "Kind", tag = "2")]
+    pub kind: i32,
+    #[prost(bytes = "vec", tag = "3")]
+    pub payload: Vec<u8>,
+}
+
+/// ProstError describes failures decoding protobuf envelopes
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProstError {
+    /// Envelope or payload could not be decoded
+    Decode(prost::DecodeError),
+    /// Envelope has an unspecified or unrecognised kind field
+    UnknownKind(i32),
+    /// Envelope ID does not fit the request ID type
+    InvalidId(u64),
+}
+
+impl fmt::Display for ProstError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProstError::Decode(e) => write!(f, "decode error: {}", e),
+            ProstError::UnknownKind(k) => write!(f, "unknown envelope kind: {}", k),
+            ProstError::InvalidId(id) => write!(f, "invalid envelope id: {}", id),
+        }
+    }
+}
+
+impl std::error::Error for ProstError {}
+
+impl From<prost::DecodeError> for ProstError {
+    fn from(e: prost::DecodeError) -> Self {
+        ProstError::Decode(e)
+    }
+}
+
+/// ProstCodec encodes protobuf request and response messages in a protobuf Envelope
+pub struct ProstCodec<ReqId, Req, Resp> {
+    _id: PhantomData<ReqId>,
+    _req: PhantomData<Req>,
+    _resp: PhantomData<Resp>,
+}
+
+impl<ReqId, Req, Resp> ProstCodec<ReqId, Req, Resp> {
+    /// Create a new protobuf codec
+    pub fn new() -> Self {
+        ProstCodec {
+            _id: PhantomData,
+            _req: PhantomData,
+            _resp: PhantomData,
+        }
+    }
+}
+
+impl<ReqId, Req, Resp> Default for ProstCodec<ReqId, Req, Resp> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ReqId, Req, Resp> Clone for ProstCodec<ReqId, Req, Resp> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<ReqId, Req, Resp> Codec<ReqId, Req, Resp> for ProstCodec<ReqId, Req, Resp>
+where
+    ReqId: Copy + Into<u64> + TryFrom<u64>,
+    Req: Message + Default,
+    Resp: Message + Default,
+{
+    type Error = ProstError;
+
+    fn encode(&self, id: &ReqId, msg: &Muxed<Req, Resp>) -> Result<Vec<u8>, ProstError> {
+        let (kind, payload) = match msg {
+            Muxed::Request(req) => (Kind::Request, req.encode_to_vec()),
+            Muxed::Response(resp) => (Kind::Response, resp.encode_to_vec()),
+        };
+
+        let e = Envelope {
+            id: (*id).into(),
+            kind: kind as i32,
+            payload,
+        };
+
+        Ok(e.encode_to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(ReqId, Muxed<Req, Resp>), ProstError> {
+        let e = Envelope::decode(data)?;
+
+        let id = ReqId::try_from(e.id).map_err(|_| ProstError::InvalidId(e.id))?;
+
+        let msg = match Kind::try_from(e.kind) {
+            Ok(Kind::Request) => Muxed::Request(Req::decode(&e.payload[..])?),
+            Ok(Kind::Response) => Muxed::Response(Resp::decode(&e.payload[..])?),
+            _ => return Err(ProstError::UnknownKind(e.kind)),
+        };
+
+        Ok((id, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Ping {
+        #[prost(uint32, tag = "1")]
+        seq: u32,
+    }
+
+    type Msg = Muxed<Ping, Ping>;
+
+    #[test]
+    fn test_prost_codec() {
+        let c = ProstCodec::<u16, Ping, Ping>::new();
+
+        let m: Msg = Muxed::Request(Ping { seq: 1 });
+        let encoded = c.encode(&4, &m).unwrap();
+        assert_eq!(encoded, vec![0x08, 0x04, 0x10, 0x01, 0x1a, 0x02, 0x08, 0x01]);
+        assert_eq!(c.decode(&encoded).unwrap(), (4, m));
+
+        let m: Msg = Muxed::Response(Ping { seq: 2 });
+        let encoded = c.encode(&5, &m).unwrap();
+        assert_eq!(encoded, vec![0x08, 0x05, 0x10, 0x02, 0x1a, 0x02, 0x08, 0x02]);
+        assert_eq!(c.decode(&encoded).unwrap(), (5, m));
+    }
+
+    #[test]
+    fn test_prost_errors() {
+        let c = ProstCodec::<u16, Ping, Ping>::new();
+
+        let e = Envelope { id: 1, kind: 0, payload: vec![] }.encode_to_vec();
+        assert_eq!(c.decode(&e), Err(ProstError::UnknownKind(0)));
+
+        let e = Envelope { id: 0x10000, kind: 1, payload: vec![] }.encode_to_vec();
+        assert_eq!(c.decode(&e), Err(ProstError::InvalidId(0x10000)));
+    }
+}
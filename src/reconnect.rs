@@ -0,0 +1,567 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::channel::{mpsc, oneshot};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use futures_timer::Delay;
+
+use crate::connector::Connector;
+use crate::pool::Dialed;
+use crate::stream::{Error, Incoming, StreamConnector};
+
+/// ConnectionState describes changes in the state of a reconnecting connector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    /// Dialing a new connection
+    Connecting,
+    /// Connection established
+    Connected,
+    /// Connection lost, a reconnect will be attempted
+    Disconnected,
+    /// Reconnect attempts exhausted or the driver stopped, no further connections will be made
+    Failed,
+}
+
+/// Backoff configures delays between reconnect attempts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    /// Delay before the first retry
+    pub initial: Duration,
+    /// Maximum delay between retries
+    pub max: Duration,
+    /// Multiplier applied to the delay after each failed attempt
+    pub multiplier: u32,
+    /// Maximum number of consecutive failed attempts before giving up
+    pub max_attempts: Option<usize>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Compute the delay following the provided number of failed attempts
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let mut d = self.initial;
+        for _ in 1..attempt {
+            d = d.checked_mul(self.multiplier).unwrap_or(self.max);
+            if d >= self.max {
+                return self.max;
+            }
+        }
+        d.min(self.max)
+    }
+}
+
+/// ReconnectDial is a user supplied function to establish a new connection
+pub type ReconnectDial<ReqId, Target, Req, Resp, Ctx> =
+    Arc<dyn Fn() -> BoxFuture<'static, Result<Dialed<ReqId, Target, Req, Resp, Ctx>, Error>> + Send + Sync>;
+
+type Replay<Req> = Arc<dyn Fn(&Req) -> bool + Send + Sync>;
+
+struct Shared<ReqId, Target, Req, Resp, Ctx> {
+    conn: Option<StreamConnector<ReqId, Target, Req, Resp, Ctx>>,
+    failed: bool,
+    waiters: Vec<oneshot::Sender<()>>,
+    listeners: Vec<mpsc::UnboundedSender<ConnectionState>>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Shared<ReqId, Target, Req, Resp, Ctx> {
+    fn emit(&mut self, state: ConnectionState) {
+        debug!("Connection state: {:?}", state);
+        self.listeners.retain(|l| l.unbounded_send(state).is_ok());
+    }
+
+    fn wake(&mut self) {
+        for w in self.waiters.drain(..) {
+            let _ = w.send(());
+        }
+    }
+}
+
+type SharedState<ReqId, Target, Req, Resp, Ctx> = Arc<Mutex<Shared<ReqId, Target, Req, Resp, Ctx>>>;
+
+type IncomingReceiver<ReqId, Target, Req, Ctx> = Arc<Mutex<mpsc::Receiver<Incoming<ReqId, Target, Req, Ctx>>>>;
+
+/// Reconnecting is a Connector over a connection that is redialed with backoff when lost.
+/// Requests made while disconnected wait for the next connection. Requests in flight when a
+/// connection is lost fail with Error::ConnectionLost, unless accepted by the replay predicate
+/// (see `with_replay`) in which case they are re-sent once reconnected.
+pub struct Reconnecting<ReqId, Target, Req, Resp, Ctx> {
+    shared: SharedState<ReqId, Target, Req, Resp, Ctx>,
+    incoming: IncomingReceiver<ReqId, Target, Req, Ctx>,
+    replay: Option<Replay<Req>>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Clone for Reconnecting<ReqId, Target, Req, Resp, Ctx> {
+    fn clone(&self) -> Self {
+        Reconnecting {
+            shared: self.shared.clone(),
+            incoming: self.incoming.clone(),
+            replay: self.replay.clone(),
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Reconnecting<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Clone + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Create a new reconnecting connector using the provided dial function and backoff configuration.
+    /// This returns the connector and a driver that must be polled (or spawned) to manage connections.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<D>(dial: D, backoff: Backoff) -> (Self, ReconnectDriver<ReqId, Target, Req, Resp, Ctx>)
+    where
+        D: Fn() -> BoxFuture<'static, Result<Dialed<ReqId, Target, Req, Resp, Ctx>, Error>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            conn: None,
+            failed: false,
+            waiters: vec![],
+            listeners: vec![],
+        }));
+        let (tx, rx) = mpsc::channel(0);
+
+        let conn = Reconnecting {
+            shared: shared.clone(),
+            incoming: Arc::new(Mutex::new(rx)),
+            replay: None,
+        };
+
+        let driver = ReconnectDriver {
+            shared,
+            dial: Arc::new(dial),
+            backoff,
+            incoming: tx,
+        };
+
+        (conn, driver)
+    }
+
+    /// Set a predicate identifying idempotent requests, to be re-sent if the connection is lost
+    pub fn with_replay<F>(mut self, replay: F) -> Self
+    where
+        F: Fn(&Req) -> bool + Send + Sync + 'static,
+    {
+        self.replay = Some(Arc::new(replay));
+        self
+    }
+
+    /// Subscribe to connection state change events
+    pub fn events(&self) -> mpsc::UnboundedReceiver<ConnectionState> {
+        let (tx, rx) = mpsc::unbounded();
+        self.shared.lock().unwrap().listeners.push(tx);
+        rx
+    }
+
+    /// Check whether a connection is currently established
+    pub fn is_connected(&self) -> bool {
+        self.shared.lock().unwrap().conn.is_some()
+    }
+
+    /// Fetch the current connection, waiting for a connection to be established if required
+    async fn connection(&self) -> Result<StreamConnector<ReqId, Target, Req, Resp, Ctx>, Error> {
+        loop {
+            let rx = {
+                let mut shared = self.shared.lock().unwrap();
+                // Closed connections are skipped while the driver notices the loss and reconnects
+                match (&shared.conn, shared.failed) {
+                    (Some(c), _) if !c.is_closed() => return Ok(c.clone()),
+                    (_, true) => return Err(Error::ConnectionLost),
+                    _ => {
+                        let (tx, rx) = oneshot::channel();
+                        shared.waiters.push(tx);
+                        rx
+                    }
+                }
+            };
+
+            let _ = rx.await;
+        }
+    }
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, Ctx> Connector<ReqId, Target, Req, Resp, Error, Ctx>
+    for Reconnecting<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Clone + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    async fn request(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req,
+    ) -> Result<Resp, Error> {
+        loop {
+            let mut conn = self.connection().await?;
+
+            let res = conn.request(ctx.clone(), req_id.clone(), target.clone(), req.clone()).await;
+
+            match (&res, &self.replay) {
+                (Err(Error::ConnectionLost), Some(replay)) if replay(&req) => {
+                    debug!("Connection lost, replaying request id: '{:?}'", req_id);
+                }
+                _ => return res,
+            }
+        }
+    }
+
+    async fn respond(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp,
+    ) -> Result<(), Error> {
+        let conn = self.shared.lock().unwrap().conn.clone();
+
+        match conn {
+            Some(mut c) => c.respond(ctx, req_id, target, resp).await,
+            None => Err(Error::ConnectionLost),
+        }
+    }
+}
+
+// Stream implementation to allow polling for incoming requests
+impl<ReqId, Target, Req, Resp, Ctx> Stream for Reconnecting<ReqId, Target, Req, Resp, Ctx> {
+    type Item = Incoming<ReqId, Target, Req, Ctx>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.incoming.lock().unwrap().poll_next_unpin(cx)
+    }
+}
+
+// Running marks the connector failed when the driver exits or is dropped, closing the current connection
+// and waking requests waiting on a reconnect that will no longer happen
+struct Running<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    shared: SharedState<ReqId, Target, Req, Resp, Ctx>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Drop for Running<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(c) = shared.conn.take() {
+            c.close();
+        }
+
+        shared.failed = true;
+        shared.emit(ConnectionState::Failed);
+        shared.wake();
+    }
+}
+
+/// ReconnectDriver dials and runs connections for a Reconnecting connector
+pub struct ReconnectDriver<ReqId, Target, Req, Resp, Ctx> {
+    shared: SharedState<ReqId, Target, Req, Resp, Ctx>,
+    dial: ReconnectDial<ReqId, Target, Req, Resp, Ctx>,
+    backoff: Backoff,
+    incoming: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> ReconnectDriver<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Run the connection manager, resolving with the last dial error once reconnect attempts are exhausted.
+    /// Once this exits or is dropped the current connection is closed and requests fail
+    pub async fn run(self) -> Result<(), Error> {
+        let _running = Running { shared: self.shared.clone() };
+        let mut attempt = 0;
+
+        loop {
+            self.shared.lock().unwrap().emit(ConnectionState::Connecting);
+
+            let (conn, driver) = match (self.dial)().await {
+                Ok(d) => d,
+                Err(e) => {
+                    attempt += 1;
+                    warn!("Connection attempt {} failed: {:?}", attempt, e);
+
+                    if self.backoff.max_attempts.map(|m| attempt >= m).unwrap_or(false) {
+                        return Err(e);
+                    }
+
+                    Delay::new(self.backoff.delay(attempt)).await;
+                    continue;
+                }
+            };
+
+            attempt = 0;
+
+            {
+                let mut shared = self.shared.lock().unwrap();
+                shared.conn = Some(conn.clone());
+                shared.emit(ConnectionState::Connected);
+                shared.wake();
+            }
+
+            // Forward incoming requests until the connection is lost
+            let closing = conn.clone();
+            let mut incoming = conn;
+            let mut tx = self.incoming.clone();
+            let forward = async move {
+                while let Some(i) = incoming.next().await {
+                    if tx.send(i).await.is_err() {
+                        break;
+                    }
+                }
+                Ok::<(), Error>(())
+            };
+
+            let res = future::select(driver, forward.boxed()).await.factor_first().0;
+            debug!("Connection closed: {:?}", res);
+
+            // The driver is dropped if forwarding ends first, so close the connection here
+            closing.close();
+
+            let mut shared = self.shared.lock().unwrap();
+            shared.conn = None;
+            shared.emit(ConnectionState::Disconnected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::LengthPrefix;
+
+    type Conn = Reconnecting<u16, u32, Vec<u8>, Vec<u8>, ()>;
+    type Driver = ReconnectDriver<u16, u32, Vec<u8>, Vec<u8>, ()>;
+
+    // Start a server that drops the first connection on receipt of a request,
+    // and responds to requests on subsequent connections
+    async fn server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        task::spawn(async move {
+            let mut index = 0;
+            while let Ok((s, _)) = listener.accept().await {
+                let (mut conn, driver) = StreamConnector::<u16, u32, Vec<u8>, Vec<u8>, ()>::new(
+                    s,
+                    EnvelopeCodec,
+                    LengthPrefix::new(),
+                    0x11,
+                    (),
+                );
+                let (driver, abort) = future::abortable(driver.run());
+                task::spawn(driver);
+
+                let drop_connection = index == 0;
+                index += 1;
+
+                task::spawn(async move {
+                    while let Some((id, from, req, _ctx)) = conn.next().await {
+                        if drop_connection {
+                            abort.abort();
+                            break;
+                        }
+                        conn.respond((), id, from, req).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    fn connector(addr: SocketAddr, dials: Arc<AtomicUsize>) -> (Conn, Driver) {
+        Reconnecting::new(
+            move || {
+                dials.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let s = TcpStream::connect(addr).await?;
+                    let (conn, driver) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 0x22, ());
+                    Ok((conn, driver.run().boxed()))
+                }
+                .boxed()
+            },
+            Backoff {
+                initial: Duration::from_millis(1),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_reconnect_fail_pending() {
+        task::block_on(async {
+            let addr = server().await;
+            let dials = Arc::new(AtomicUsize::new(0));
+
+            let (mut conn, driver) = connector(addr, dials.clone());
+            let mut events = conn.events();
+            task::spawn(driver.run());
+
+            // In-flight requests fail when the connection is lost
+            let res = conn.request((), 1, 0x11, vec![1]).await;
+            assert!(matches!(res, Err(Error::ConnectionLost)), "unexpected result: {:?}", res);
+
+            // Subsequent requests wait for reconnection
+            assert_eq!(conn.request((), 2, 0x11, vec![2]).await.unwrap(), vec![2]);
+            assert_eq!(dials.load(Ordering::SeqCst), 2);
+
+            let mut states = vec![];
+            for _ in 0..5 {
+                states.push(events.next().await.unwrap());
+            }
+            assert_eq!(
+                states,
+                vec![
+                    ConnectionState::Connecting,
+                    ConnectionState::Connected,
+                    ConnectionState::Disconnected,
+                    ConnectionState::Connecting,
+                    ConnectionState::Connected,
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_reconnect_replay() {
+        task::block_on(async {
+            let addr = server().await;
+            let dials = Arc::new(AtomicUsize::new(0));
+
+            let (conn, driver) = connector(addr, dials.clone());
+            let mut conn = conn.with_replay(|_req| true);
+            task::spawn(driver.run());
+
+            // Idempotent requests are re-sent once reconnected
+            assert_eq!(conn.request((), 1, 0x11, vec![1]).await.unwrap(), vec![1]);
+            assert_eq!(dials.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn test_reconnect_exhausted() {
+        task::block_on(async {
+            let dials = Arc::new(AtomicUsize::new(0));
+            let d = dials.clone();
+
+            let (mut conn, driver) = Conn::new(
+                move || {
+                    d.fetch_add(1, Ordering::SeqCst);
+                    future::err(Error::Closed).boxed()
+                },
+                Backoff {
+                    initial: Duration::from_millis(1),
+                    max_attempts: Some(3),
+                    ..Default::default()
+                },
+            );
+            let events = conn.events();
+
+            // Driver exits with the last dial error once attempts are exhausted
+            assert!(matches!(driver.run().await, Err(Error::Closed)));
+            assert_eq!(dials.load(Ordering::SeqCst), 3);
+
+            // And requests fail rather than waiting for a connection
+            let res = conn.request((), 1, 0x11, vec![1]).await;
+            assert!(matches!(res, Err(Error::ConnectionLost)), "unexpected result: {:?}", res);
+
+            let states: Vec<_> = events.take(4).collect().await;
+            assert_eq!(states.last(), Some(&ConnectionState::Failed));
+        });
+    }
+
+    #[test]
+    fn test_reconnect_driver_dropped() {
+        task::block_on(async {
+            // Server accepts a connection without responding, signalling once data is received
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (tx, mut received) = mpsc::unbounded();
+
+            task::spawn(async move {
+                let (mut s, _) = listener.accept().await.unwrap();
+                let mut buff = [0u8; 64];
+                while s.read(&mut buff).await.map(|n| n > 0).unwrap_or(false) {
+                    let _ = tx.unbounded_send(());
+                }
+            });
+
+            let (mut conn, driver) = connector(addr, Arc::new(AtomicUsize::new(0)));
+            let events = conn.events();
+            let (driver, abort) = future::abortable(driver.run());
+            task::spawn(driver);
+
+            let mut c = conn.clone();
+            let req = task::spawn(async move { c.request((), 1, 0x11, vec![1]).await });
+            received.next().await;
+
+            // Dropping the driver mid-request fails pending and subsequent requests
+            abort.abort();
+            assert!(matches!(req.await, Err(Error::ConnectionLost)));
+
+            let res = conn.request((), 2, 0x11, vec![2]).await;
+            assert!(matches!(res, Err(Error::ConnectionLost)), "unexpected result: {:?}", res);
+            assert!(!conn.is_connected());
+
+            let states: Vec<_> = events.take(3).collect().await;
+            assert_eq!(states.last(), Some(&ConnectionState::Failed));
+        });
+    }
+
+    #[test]
+    fn test_backoff() {
+        let b = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+            multiplier: 2,
+            max_attempts: None,
+        };
+
+        assert_eq!(b.delay(1), Duration::from_millis(10));
+        assert_eq!(b.delay(2), Duration::from_millis(20));
+        assert_eq!(b.delay(3), Duration::from_millis(40));
+        assert_eq!(b.delay(4), Duration::from_millis(50));
+        assert_eq!(b.delay(100), Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,501 @@
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use crate::codec::{EnvelopeId, Kind};
+use crate::connector::Connector;
+use crate::stream::Incoming;
+
+/// SealError describes failures sealing or opening messages
+#[derive(Debug, Clone, PartialEq)]
+pub enum SealError {
+    /// No key is available for the target
+    NoKey,
+    /// Message failed verification or decryption
+    Rejected,
+    /// Message could not be sealed
+    Seal,
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SealError::NoKey => write!(f, "no key for target"),
+            SealError::Rejected => write!(f, "message rejected"),
+            SealError::Seal => write!(f, "message sealing failed"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+/// Error describes failures of a sealed connector, either sealing or opening messages or from the
+/// underlying connector
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error<E> {
+    /// Message sealing or verification failed
+    Seal(SealError),
+    /// Underlying connector error
+    Conn(E),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Seal(e) => write!(f, "seal error: {}", e),
+            Error::Conn(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: fmt::Display + Debug> std::error::Error for Error<E> {}
+
+impl<E> From<SealError> for Error<E> {
+    fn from(e: SealError) -> Self {
+        Error::Seal(e)
+    }
+}
+
+/// Sealer implements per-message authentication or encryption of message bytes using a provided key.
+/// The associated data must be bound to the sealed message, this carries the message Kind and request ID
+/// so requests cannot be replayed as responses and responses cannot be substituted for those of other requests
+pub trait Sealer {
+    type Key;
+
+    /// Sign or encrypt an outgoing message, binding the provided associated data
+    fn seal(&self, key: &Self::Key, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError>;
+
+    /// Verify or decrypt an incoming message, failing with SealError::Rejected on verification failure
+    /// or where the message was sealed with different associated data
+    fn open(&self, key: &Self::Key, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError>;
+}
+
+// Build the associated data binding a message to its kind and request ID
+fn associated_data<ReqId: EnvelopeId>(kind: Kind, id: &ReqId) -> Vec<u8> {
+    let mut ad = vec![kind as u8];
+    id.write(&mut ad);
+    ad
+}
+
+/// Keys is a user supplied function to look up the key for a given target
+pub type Keys<Target, Key> = Arc<dyn Fn(&Target) -> Option<Key> + Send + Sync>;
+
+/// Sealed wraps a byte connector with a Sealer implementation, sealing outgoing requests and responses
+/// and opening incoming ones using the key for each target.
+/// Responses failing verification are returned as errors, and incoming requests failing verification
+/// are dropped rather than passed to handlers. Errors from the underlying connector are returned as Error::Conn.
+pub struct Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S: Sealer> {
+    conn: Conn,
+    sealer: S,
+    keys: Keys<Target, S::Key>,
+
+    _req_id: PhantomData<ReqId>,
+    _req: PhantomData<Req>,
+    _resp: PhantomData<Resp>,
+    _err: PhantomData<E>,
+    _ctx: PhantomData<Ctx>,
+}
+
+impl<Req, Resp, ReqId, Target, E, Ctx, Conn, S> Clone for Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S>
+where
+    Conn: Clone,
+    S: Sealer + Clone,
+{
+    fn clone(&self) -> Self {
+        Sealed {
+            conn: self.conn.clone(),
+            sealer: self.sealer.clone(),
+            keys: self.keys.clone(),
+
+            _req_id: PhantomData,
+            _req: PhantomData,
+            _resp: PhantomData,
+            _err: PhantomData,
+            _ctx: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp, ReqId, Target, E, Ctx, Conn, S: Sealer> Unpin for Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S> {}
+
+impl<Req, Resp, ReqId, Target, E, Ctx, Conn, S> Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S>
+where
+    S: Sealer,
+{
+    /// Create a new sealed connector using the provided sealer and key lookup function
+    pub fn new<K>(conn: Conn, sealer: S, keys: K) -> Self
+    where
+        K: Fn(&Target) -> Option<S::Key> + Send + Sync + 'static,
+    {
+        Sealed {
+            conn,
+            sealer,
+            keys: Arc::new(keys),
+
+            _req_id: PhantomData,
+            _req: PhantomData,
+            _resp: PhantomData,
+            _err: PhantomData,
+            _ctx: PhantomData,
+        }
+    }
+
+    fn key(&self, target: &Target) -> Result<S::Key, SealError> {
+        (self.keys)(target).ok_or(SealError::NoKey)
+    }
+
+    fn seal(&self, key: &S::Key, kind: Kind, id: &ReqId, data: &[u8]) -> Result<Vec<u8>, SealError>
+    where
+        ReqId: EnvelopeId,
+    {
+        self.sealer.seal(key, &associated_data(kind, id), data)
+    }
+
+    fn open(&self, key: &S::Key, kind: Kind, id: &ReqId, data: &[u8]) -> Result<Vec<u8>, SealError>
+    where
+        ReqId: EnvelopeId,
+    {
+        self.sealer.open(key, &associated_data(kind, id), data)
+    }
+}
+
+#[async_trait]
+impl<Req, Resp, ReqId, Target, E, Ctx, Conn, S> Connector<ReqId, Target, Req, Resp, Error<E>, Ctx>
+    for Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S>
+where
+    ReqId: EnvelopeId + Debug + Clone + Send + 'static,
+    Target: Debug + Send + 'static,
+    Req: AsRef<[u8]> + Debug + Send + 'static,
+    Resp: AsRef<[u8]> + From<Vec<u8>> + Debug + Send + 'static,
+    E: Debug + Send + 'static,
+    Ctx: Send + 'static,
+    Conn: Connector<ReqId, Target, Vec<u8>, Vec<u8>, E, Ctx> + Send + 'static,
+    S: Sealer + Send + Sync + 'static,
+    S::Key: Send,
+{
+    /// Seal and send a request, opening the response from the target
+    async fn request(&mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req) -> Result<Resp, Error<E>> {
+        let key = self.key(&target)?;
+
+        let sealed = self.seal(&key, Kind::Request, &req_id, req.as_ref())?;
+        let resp = self.conn.request(ctx, req_id.clone(), target, sealed).await.map_err(Error::Conn)?;
+
+        let opened = self.open(&key, Kind::Response, &req_id, &resp)?;
+
+        Ok(Resp::from(opened))
+    }
+
+    /// Seal and send a response
+    async fn respond(&mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp) -> Result<(), Error<E>> {
+        let key = self.key(&target)?;
+        let sealed = self.seal(&key, Kind::Response, &req_id, resp.as_ref())?;
+
+        self.conn.respond(ctx, req_id, target, sealed).await.map_err(Error::Conn)
+    }
+}
+
+// Stream implementation to allow polling for verified incoming requests
+impl<Req, Resp, ReqId, Target, E, Ctx, Conn, S> Stream for Sealed<Req, Resp, ReqId, Target, E, Ctx, Conn, S>
+where
+    ReqId: EnvelopeId,
+    Target: Debug,
+    Req: From<Vec<u8>>,
+    Conn: Stream<Item = Incoming<ReqId, Target, Vec<u8>, Ctx>> + Unpin,
+    S: Sealer,
+{
+    type Item = Incoming<ReqId, Target, Req, Ctx>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let s = self.get_mut();
+
+        loop {
+            let (id, target, req, ctx) = match s.conn.poll_next_unpin(cx) {
+                Poll::Ready(Some(i)) => i,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match s.key(&target).and_then(|key| s.open(&key, Kind::Request, &id, &req)) {
+                Ok(r) => return Poll::Ready(Some((id, target, Req::from(r), ctx))),
+                Err(e) => warn!("Dropping request from {:?}: {}", target, e),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hmac")]
+pub use self::hmac_sealer::HmacSealer;
+
+#[cfg(feature = "hmac")]
+mod hmac_sealer {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use super::{SealError, Sealer};
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    const TAG_LEN: usize = 32;
+
+    /// HmacSealer authenticates messages with an appended HMAC-SHA256 tag, messages are not encrypted
+    #[derive(Debug, Clone, Default)]
+    pub struct HmacSealer;
+
+    impl HmacSealer {
+        fn mac(key: &[u8], ad: &[u8], data: &[u8]) -> HmacSha256 {
+            let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+            mac.update(&(ad.len() as u32).to_be_bytes());
+            mac.update(ad);
+            mac.update(data);
+            mac
+        }
+    }
+
+    impl Sealer for HmacSealer {
+        type Key = Vec<u8>;
+
+        fn seal(&self, key: &Vec<u8>, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            let tag = Self::mac(key, ad, data).finalize().into_bytes();
+
+            let mut sealed = data.to_vec();
+            sealed.extend_from_slice(&tag);
+
+            Ok(sealed)
+        }
+
+        fn open(&self, key: &Vec<u8>, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            if data.len() < TAG_LEN {
+                return Err(SealError::Rejected);
+            }
+            let (data, tag) = data.split_at(data.len() - TAG_LEN);
+
+            Self::mac(key, ad, data).verify_slice(tag).map_err(|_| SealError::Rejected)?;
+
+            Ok(data.to_vec())
+        }
+    }
+}
+
+#[cfg(feature = "aead")]
+pub use self::aead_sealer::ChaChaSealer;
+
+#[cfg(feature = "aead")]
+mod aead_sealer {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+    use rand::RngCore;
+
+    use super::{SealError, Sealer};
+
+    const NONCE_LEN: usize = 12;
+
+    /// ChaChaSealer encrypts and authenticates messages using ChaCha20-Poly1305 with a random nonce
+    /// prepended to each message
+    #[derive(Debug, Clone, Default)]
+    pub struct ChaChaSealer;
+
+    impl Sealer for ChaChaSealer {
+        type Key = [u8; 32];
+
+        fn seal(&self, key: &[u8; 32], ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+
+            let mut nonce = [0u8; NONCE_LEN];
+            rand::thread_rng().fill_bytes(&mut nonce);
+
+            let payload = Payload { msg: data, aad: ad };
+            let encrypted = cipher.encrypt(Nonce::from_slice(&nonce), payload).map_err(|_| SealError::Seal)?;
+
+            let mut sealed = nonce.to_vec();
+            sealed.extend_from_slice(&encrypted);
+
+            Ok(sealed)
+        }
+
+        fn open(&self, key: &[u8; 32], ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            if data.len() < NONCE_LEN {
+                return Err(SealError::Rejected);
+            }
+            let (nonce, encrypted) = data.split_at(NONCE_LEN);
+
+            let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+            let payload = Payload { msg: encrypted, aad: ad };
+
+            cipher.decrypt(Nonce::from_slice(nonce), payload).map_err(|_| SealError::Rejected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::LengthPrefix;
+    use crate::mock::{MockConnector, MockTransaction};
+    use crate::stream::{self, StreamConnector};
+
+    /// Checksum appends a single byte tag over the key, associated data and data
+    #[derive(Clone)]
+    struct Checksum;
+
+    impl Checksum {
+        fn tag(key: u8, ad: &[u8], data: &[u8]) -> u8 {
+            ad.iter().chain(data).fold(key, |a, b| a.wrapping_mul(31).wrapping_add(*b))
+        }
+    }
+
+    impl Sealer for Checksum {
+        type Key = u8;
+
+        fn seal(&self, key: &u8, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            let mut sealed = data.to_vec();
+            sealed.push(Self::tag(*key, ad, data));
+            Ok(sealed)
+        }
+
+        fn open(&self, key: &u8, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, SealError> {
+            match data.split_last() {
+                Some((tag, data)) if *tag == Self::tag(*key, ad, data) => Ok(data.to_vec()),
+                _ => Err(SealError::Rejected),
+            }
+        }
+    }
+
+    fn keys(target: &u16) -> Option<u8> {
+        match target {
+            1 => Some(0xaa),
+            _ => None,
+        }
+    }
+
+    fn seal(kind: Kind, id: u64, data: &[u8]) -> Vec<u8> {
+        Checksum.seal(&0xaa, &associated_data(kind, &id), data).unwrap()
+    }
+
+    type Mock = MockConnector<u16, Vec<u8>, Vec<u8>, (), ()>;
+    type SealedMock = Sealed<Vec<u8>, Vec<u8>, u64, u16, (), (), Mock, Checksum>;
+
+    #[test]
+    fn test_sealed() {
+        let mut m = Mock::new();
+        let mut s = SealedMock::new(m.clone(), Checksum, keys);
+
+        m.expect(vec![
+            MockTransaction::request(1, seal(Kind::Request, 0, &[1, 2]), Ok((seal(Kind::Response, 0, &[3, 4]), ()))),
+            MockTransaction::response(1, seal(Kind::Response, 0, &[5]), None),
+            MockTransaction::request(1, seal(Kind::Request, 1, &[1]), Err(())),
+        ]);
+
+        // Requests are sealed and responses opened
+        let resp = block_on(s.request((), 0, 1, vec![1, 2])).unwrap();
+        assert_eq!(resp, vec![3, 4]);
+
+        block_on(s.respond((), 0, 1, vec![5])).unwrap();
+
+        // Connector errors are passed through
+        assert_eq!(block_on(s.request((), 1, 1, vec![1])), Err(Error::Conn(())));
+
+        m.finalise();
+    }
+
+    #[test]
+    fn test_sealed_rejected() {
+        let mut m = Mock::new();
+        let mut s = SealedMock::new(m.clone(), Checksum, keys);
+
+        m.expect(vec![
+            // Tampered responses
+            MockTransaction::request(1, seal(Kind::Request, 0, &[1]), Ok((vec![3, 4, 0], ()))),
+            // Requests reflected as responses
+            MockTransaction::request(1, seal(Kind::Request, 1, &[1]), Ok((seal(Kind::Request, 1, &[1]), ()))),
+            // Responses to other requests substituted
+            MockTransaction::request(1, seal(Kind::Request, 2, &[1]), Ok((seal(Kind::Response, 0, &[3, 4]), ()))),
+        ]);
+
+        assert_eq!(block_on(s.request((), 0, 1, vec![1])), Err(Error::Seal(SealError::Rejected)));
+        assert_eq!(block_on(s.request((), 1, 1, vec![1])), Err(Error::Seal(SealError::Rejected)));
+        assert_eq!(block_on(s.request((), 2, 1, vec![1])), Err(Error::Seal(SealError::Rejected)));
+
+        // Targets without keys are rejected before sending
+        assert_eq!(block_on(s.request((), 3, 2, vec![1])), Err(Error::Seal(SealError::NoKey)));
+        assert_eq!(block_on(s.respond((), 3, 2, vec![1])), Err(Error::Seal(SealError::NoKey)));
+
+        m.finalise();
+    }
+
+    type Conn = StreamConnector<u16, u16, Vec<u8>, Vec<u8>, ()>;
+    type SealedConn = Sealed<Vec<u8>, Vec<u8>, u16, u16, stream::Error, (), Conn, Checksum>;
+
+    #[test]
+    fn test_sealed_incoming() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            task::spawn(async move {
+                let (s, _) = listener.accept().await.unwrap();
+                let (conn, driver): (Conn, _) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 1, ());
+                task::spawn(driver.run());
+
+                let mut conn: SealedConn = Sealed::new(conn, Checksum, keys);
+
+                // Only verified requests are passed to the handler
+                while let Some((id, from, mut req, _ctx)) = conn.next().await {
+                    assert_eq!(id, 2);
+                    req.reverse();
+                    conn.respond((), id, from, req).await.unwrap();
+                }
+            });
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (conn, driver): (Conn, _) = StreamConnector::new(s, EnvelopeCodec, LengthPrefix::new(), 1, ());
+            task::spawn(driver.run());
+
+            // Send an unsealed request, this is never answered
+            let mut raw = conn.clone();
+            task::spawn(async move { raw.request((), 1, 1, vec![1, 2, 3]).await });
+
+            let mut conn: SealedConn = Sealed::new(conn, Checksum, keys);
+            let resp = conn.request((), 2, 1, vec![1, 2, 3]).await.unwrap();
+            assert_eq!(resp, vec![3, 2, 1]);
+        });
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_sealer() {
+        let key = b"key".to_vec();
+
+        let sealed = HmacSealer.seal(&key, &[1, 0], &[1, 2, 3]).unwrap();
+        assert_eq!(HmacSealer.open(&key, &[1, 0], &sealed), Ok(vec![1, 2, 3]));
+
+        assert_eq!(HmacSealer.open(&b"other".to_vec(), &[1, 0], &sealed), Err(SealError::Rejected));
+        assert_eq!(HmacSealer.open(&key, &[2, 0], &sealed), Err(SealError::Rejected));
+        assert_eq!(HmacSealer.open(&key, &[1, 1], &sealed), Err(SealError::Rejected));
+        assert_eq!(HmacSealer.open(&key, &[1, 0], &sealed[1..]), Err(SealError::Rejected));
+    }
+
+    #[cfg(feature = "aead")]
+    #[test]
+    fn test_chacha_sealer() {
+        let key = [0x11; 32];
+
+        let sealed = ChaChaSealer.seal(&key, &[1, 0], &[1, 2, 3]).unwrap();
+        assert!(!sealed.windows(3).any(|w| w == [1, 2, 3]));
+        assert_eq!(ChaChaSealer.open(&key, &[1, 0], &sealed), Ok(vec![1, 2, 3]));
+
+        assert_eq!(ChaChaSealer.open(&[0x22; 32], &[1, 0], &sealed), Err(SealError::Rejected));
+        assert_eq!(ChaChaSealer.open(&key, &[2, 0], &sealed), Err(SealError::Rejected));
+        assert_eq!(ChaChaSealer.open(&key, &[1, 1], &sealed), Err(SealError::Rejected));
+        assert_eq!(ChaChaSealer.open(&key, &[1, 0], &sealed[..4]), Err(SealError::Rejected));
+    }
+}
@@ -0,0 +1,161 @@
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::codec::Codec;
+use crate::muxed::Muxed;
+
+/// EnvelopeRef is the serialised form of a request ID and message
+#[derive(Serialize)]
+struct EnvelopeRef<'a, ReqId, Req, Resp> {
+    id: &'a ReqId,
+    msg: &'a Muxed<Req, Resp>,
+}
+
+/// Envelope is the deserialised form of a request ID and message
+#[derive(Deserialize)]
+struct Envelope<ReqId, Req, Resp> {
+    id: ReqId,
+    msg: Muxed<Req, Resp>,
+}
+
+macro_rules! serde_codec {
+    ($(#[$meta:meta])* $name:ident, $feature:literal, $err:ty, $encode:expr, $decode:expr) => {
+        $(#[$meta])*
+        #[cfg(feature = $feature)]
+        pub struct $name<ReqId, Req, Resp> {
+            _id: PhantomData<ReqId>,
+            _req: PhantomData<Req>,
+            _resp: PhantomData<Resp>,
+        }
+
+        #[cfg(feature = $feature)]
+        impl<ReqId, Req, Resp> $name<ReqId, Req, Resp> {
+            /// Create a new codec instance
+            pub fn new() -> Self {
+                $name {
+                    _id: PhantomData,
+                    _req: PhantomData,
+                    _resp: PhantomData,
+                }
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl<ReqId, Req, Resp> Default for $name<ReqId, Req, Resp> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl<ReqId, Req, Resp> Clone for $name<ReqId, Req, Resp> {
+            fn clone(&self) -> Self {
+                Self::new()
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl<ReqId, Req, Resp> Codec<ReqId, Req, Resp> for $name<ReqId, Req, Resp>
+        where
+            ReqId: Serialize + DeserializeOwned,
+            Req: Serialize + DeserializeOwned,
+            Resp: Serialize + DeserializeOwned,
+        {
+            type Error = $err;
+
+            fn encode(&self, id: &ReqId, msg: &Muxed<Req, Resp>) -> Result<Vec<u8>, Self::Error> {
+                ($encode)(&EnvelopeRef { id, msg })
+            }
+
+            fn decode(&self, data: &[u8]) -> Result<(ReqId, Muxed<Req, Resp>), Self::Error> {
+                let e: Envelope<ReqId, Req, Resp> = ($decode)(data)?;
+                Ok((e.id, e.msg))
+            }
+        }
+    };
+}
+
+serde_codec!(
+    /// BincodeCodec encodes envelopes using bincode
+    BincodeCodec, "bincode", bincode::Error, bincode::serialize, bincode::deserialize
+);
+
+serde_codec!(
+    /// CborCodec encodes envelopes using CBOR
+    CborCodec, "cbor", serde_cbor::Error, serde_cbor::to_vec, serde_cbor::from_slice
+);
+
+serde_codec!(
+    /// JsonCodec encodes envelopes using JSON
+    JsonCodec, "json", serde_json::Error, serde_json::to_vec, serde_json::from_slice
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Ping {
+        seq: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Pong {
+        seq: u32,
+        msg: String,
+    }
+
+    type Msg = Muxed<Ping, Pong>;
+
+    fn check<C: Codec<u16, Ping, Pong>>(c: C, req: &[u8], resp: &[u8]) {
+        let m: Msg = Muxed::Request(Ping { seq: 1 });
+        assert_eq!(c.encode(&4, &m).unwrap(), req);
+        assert_eq!(c.decode(req).unwrap(), (4, m));
+
+        let m: Msg = Muxed::Response(Pong { seq: 2, msg: "hi".to_string() });
+        assert_eq!(c.encode(&5, &m).unwrap(), resp);
+        assert_eq!(c.decode(resp).unwrap(), (5, m));
+    }
+
+    #[test]
+    #[cfg(feature = "bincode")]
+    fn test_bincode() {
+        check(
+            BincodeCodec::new(),
+            &[0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+            &[
+                0x05, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, b'h', b'i',
+            ],
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cbor")]
+    fn test_cbor() {
+        check(
+            CborCodec::new(),
+            &[
+                0xa2, 0x62, b'i', b'd', 0x04, 0x63, b'm', b's', b'g', 0xa1, 0x67, b'R', b'e', b'q',
+                b'u', b'e', b's', b't', 0xa1, 0x63, b's', b'e', b'q', 0x01,
+            ],
+            &[
+                0xa2, 0x62, b'i', b'd', 0x05, 0x63, b'm', b's', b'g', 0xa1, 0x68, b'R', b'e', b's',
+                b'p', b'o', b'n', b's', b'e', 0xa2, 0x63, b's', b'e', b'q', 0x02, 0x63, b'm', b's',
+                b'g', 0x62, b'h', b'i',
+            ],
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json() {
+        check(
+            JsonCodec::new(),
+            br#"{"id":4,"msg":{"Request":{"seq":1}}}"#,
+            br#"{"id":5,"msg":{"Response":{"seq":2,"msg":"hi"}}}"#,
+        );
+    }
+}
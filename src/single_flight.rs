@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+
+use crate::connector::Connector;
+
+type Waiters<Resp, E> = Vec<oneshot::Sender<Result<Resp, E>>>;
+
+type InFlight<Target, Req, Resp, E> = Arc<Mutex<HashMap<(Target, Req), Waiters<Resp, E>>>>;
+
+/// SingleFlight wraps a connector, coalescing concurrent identical (Target, Req) requests into a single
+/// underlying request with the result returned to every caller.
+/// The ReqId and Ctx of the first caller are used for the underlying request, those of later callers are ignored.
+pub struct SingleFlight<ReqId, Target, Req, Resp, E, Ctx, Conn> {
+    conn: Conn,
+    in_flight: InFlight<Target, Req, Resp, E>,
+
+    _req_id: PhantomData<ReqId>,
+    _ctx: PhantomData<Ctx>,
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Clone for SingleFlight<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    Conn: Clone,
+{
+    fn clone(&self) -> Self {
+        SingleFlight {
+            conn: self.conn.clone(),
+            in_flight: self.in_flight.clone(),
+            _req_id: PhantomData,
+            _ctx: PhantomData,
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> SingleFlight<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    Target: Eq + Hash,
+    Req: Eq + Hash,
+{
+    /// Create a new single flight wrapper over the provided connector
+    pub fn new(conn: Conn) -> Self {
+        SingleFlight {
+            conn,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            _req_id: PhantomData,
+            _ctx: PhantomData,
+        }
+    }
+
+    /// Fetch the number of distinct requests currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+}
+
+/// Flight removes an in-flight entry when the leading request completes or is dropped,
+/// so that waiters on a dropped request may retry
+struct Flight<Target: Eq + Hash, Req: Eq + Hash, Resp, E> {
+    key: Option<(Target, Req)>,
+    in_flight: InFlight<Target, Req, Resp, E>,
+}
+
+impl<Target: Eq + Hash, Req: Eq + Hash, Resp, E> Flight<Target, Req, Resp, E> {
+    fn complete(mut self) -> Waiters<Resp, E> {
+        let key = self.key.take().unwrap();
+        self.in_flight.lock().unwrap().remove(&key).unwrap_or_default()
+    }
+}
+
+impl<Target: Eq + Hash, Req: Eq + Hash, Resp, E> Drop for Flight<Target, Req, Resp, E> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.in_flight.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, E, Ctx, Conn> Connector<ReqId, Target, Req, Resp, E, Ctx>
+    for SingleFlight<ReqId, Target, Req, Resp, E, Ctx, Conn>
+where
+    ReqId: Debug + Send + 'static,
+    Target: Eq + Hash + Debug + Clone + Send + 'static,
+    Req: Eq + Hash + Debug + Clone + Send + 'static,
+    Resp: Debug + Clone + Send + 'static,
+    E: Debug + Clone + Send + 'static,
+    Ctx: Send + 'static,
+    Conn: Connector<ReqId, Target, Req, Resp, E, Ctx> + Send + 'static,
+{
+    /// Send a request, joining an identical in-flight request where one exists
+    async fn request(&mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req) -> Result<Resp, E> {
+        let key = (target, req);
+
+        loop {
+            let rx = {
+                let mut in_flight = self.in_flight.lock().unwrap();
+
+                match in_flight.get_mut(&key) {
+                    Some(waiters) => {
+                        let (tx, rx) = oneshot::channel();
+                        waiters.push(tx);
+                        rx
+                    }
+                    None => {
+                        in_flight.insert(key.clone(), vec![]);
+                        break;
+                    }
+                }
+            };
+
+            match rx.await {
+                Ok(r) => return r,
+                // Leading request was dropped, retry
+                Err(_) => debug!("In-flight request to {:?} dropped, retrying", key.0),
+            }
+        }
+
+        let flight = Flight {
+            key: Some(key.clone()),
+            in_flight: self.in_flight.clone(),
+        };
+
+        let (target, req) = key;
+        let res = self.conn.request(ctx, req_id, target, req).await;
+
+        for w in flight.complete() {
+            let _ = w.send(res.clone());
+        }
+
+        res
+    }
+
+    /// Send a response via the underlying connector
+    async fn respond(&mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp) -> Result<(), E> {
+        self.conn.respond(ctx, req_id, target, resp).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::prelude::*;
+    use futures_timer::Delay;
+
+    use super::*;
+
+    /// Slow responds to requests after a short delay, counting requests and failing on zero
+    #[derive(Clone, Default)]
+    struct Slow(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Connector<u16, u32, u8, u8, (), ()> for Slow {
+        async fn request(&mut self, _ctx: (), _id: u16, _target: u32, req: u8) -> Result<u8, ()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Delay::new(Duration::from_millis(10)).await;
+
+            match req {
+                0 => Err(()),
+                r => Ok(r * 2),
+            }
+        }
+
+        async fn respond(&mut self, _ctx: (), _id: u16, _target: u32, _resp: u8) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    type Flights = SingleFlight<u16, u32, u8, u8, (), (), Slow>;
+
+    #[test]
+    fn test_single_flight() {
+        let slow = Slow::default();
+        let s = Flights::new(slow.clone());
+
+        let (mut a, mut b, mut c, mut d, mut e) = (s.clone(), s.clone(), s.clone(), s.clone(), s.clone());
+
+        let res = block_on(future::join5(
+            a.request((), 1, 0x11, 4),
+            b.request((), 2, 0x11, 4),
+            c.request((), 3, 0x22, 4),
+            d.request((), 4, 0x11, 5),
+            e.request((), 5, 0x11, 4),
+        ));
+
+        // Identical requests to the same target are coalesced
+        assert_eq!(res, (Ok(8), Ok(8), Ok(8), Ok(10), Ok(8)));
+        assert_eq!(slow.0.load(Ordering::SeqCst), 3);
+        assert_eq!(s.in_flight(), 0);
+
+        // Errors are returned to all callers
+        let res = block_on(future::join(a.request((), 6, 0x11, 0), b.request((), 7, 0x11, 0)));
+        assert_eq!(res, (Err(()), Err(())));
+        assert_eq!(slow.0.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_single_flight_dropped() {
+        let slow = Slow::default();
+        let s = Flights::new(slow.clone());
+
+        block_on(async {
+            let (mut a, mut b) = (s.clone(), s.clone());
+
+            let mut leader = a.request((), 1, 0x11, 4);
+            assert!(futures::poll!(&mut leader).is_pending());
+
+            let mut follower = b.request((), 2, 0x11, 4);
+            assert!(futures::poll!(&mut follower).is_pending());
+
+            // Waiters on a dropped request issue their own request
+            drop(leader);
+            assert_eq!(follower.await, Ok(8));
+            assert_eq!(slow.0.load(Ordering::SeqCst), 2);
+        });
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+
+use crate::codec::Codec;
+use crate::connector::Connector;
+use crate::framing::{FrameError, Framing};
+use crate::keepalive::Keepalive;
+use crate::mux::Mux;
+use crate::muxed::Muxed;
+
+/// Error describes failures of stream based connectors
+#[derive(Debug)]
+pub enum Error {
+    /// Underlying stream error
+    Io(io::Error),
+    /// Frame encoding or decoding error
+    Frame(FrameError),
+    /// Message encoding error, messages that fail to decode are dropped
+    Codec(String),
+    /// WebSocket protocol error
+    #[cfg(feature = "websocket")]
+    WebSocket(async_tungstenite::tungstenite::Error),
+    /// Stream has been closed
+    Closed,
+    /// Connection was lost with the request pending
+    ConnectionLost,
+    /// Request reused the ID of a pending request
+    DuplicateId(String),
+    /// Remote peer failed to respond to keepalive probes
+    KeepaliveTimeout,
+    /// Connection handshake failed
+    Handshake(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Frame(e) => write!(f, "frame error: {}", e),
+            Error::Codec(e) => write!(f, "codec error: {}", e),
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(e) => write!(f, "websocket error: {}", e),
+            Error::Closed => write!(f, "stream closed"),
+            Error::ConnectionLost => write!(f, "connection lost"),
+            Error::DuplicateId(id) => write!(f, "request id {} already pending", id),
+            Error::KeepaliveTimeout => write!(f, "keepalive timeout"),
+            Error::Handshake(e) => write!(f, "handshake failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(e: FrameError) -> Self {
+        Error::Frame(e)
+    }
+}
+
+/// Incoming is a request received from the stream, with the context bound to the stream
+pub type Incoming<ReqId, Target, Req, Ctx> = (ReqId, Target, Req, Ctx);
+
+type IncomingReceiver<ReqId, Target, Req, Ctx> = Arc<Mutex<mpsc::Receiver<Incoming<ReqId, Target, Req, Ctx>>>>;
+
+/// StreamConnector is a Connector over a framed byte stream (pipe, serial port, socket etc.).
+/// Outgoing requests and responses are encoded and written to the stream by the associated StreamDriver,
+/// incoming responses are matched with pending requests and incoming requests are available via the Stream interface.
+pub struct StreamConnector<ReqId, Target, Req, Resp, Ctx> {
+    mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+    incoming: IncomingReceiver<ReqId, Target, Req, Ctx>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> Clone for StreamConnector<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    fn clone(&self) -> Self {
+        StreamConnector {
+            mux: self.mux.clone(),
+            incoming: self.incoming.clone(),
+        }
+    }
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> StreamConnector<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Create a new connector over the provided stream, using the provided codec and framing.
+    /// Target identifies the remote peer and Ctx is passed with each incoming request.
+    /// This returns the connector and a driver that must be polled (or spawned) to run the connection.
+    #[allow(clippy::new_ret_no_self, clippy::type_complexity)]
+    pub fn new<S, C, F>(
+        stream: S, codec: C, framing: F, target: Target, ctx: Ctx,
+    ) -> (Self, StreamDriver<ReqId, Target, Req, Resp, Ctx, S, C, F>)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+        C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+        F: Framing + Clone + Send + 'static,
+    {
+        let (conn, mux, tx) = Self::unbound();
+
+        let driver = StreamDriver {
+            mux,
+            incoming: tx,
+            stream,
+            codec,
+            framing,
+            target,
+            ctx,
+            keepalive: None,
+        };
+
+        (conn, driver)
+    }
+
+    /// Close the connection, failing pending and subsequent requests with Error::ConnectionLost.
+    /// This is used where the driver is stopped without running to completion
+    pub fn close(&self) {
+        self.mux.close(|| Error::ConnectionLost);
+    }
+
+    /// Check whether the connection has been closed
+    pub fn is_closed(&self) -> bool {
+        self.mux.is_closed()
+    }
+
+    /// Create an unbound connector, returning the mux and incoming request channel for use by a transport driver
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn unbound() -> (
+        Self,
+        Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+        mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>,
+    ) {
+        let mux = Mux::new(|id| Error::DuplicateId(format!("{:?}", id)));
+        let (tx, rx) = mpsc::channel(0);
+
+        let conn = StreamConnector {
+            mux: mux.clone(),
+            incoming: Arc::new(Mutex::new(rx)),
+        };
+
+        (conn, mux, tx)
+    }
+}
+
+/// Dispatch a decoded message, responses are consumed by the mux and requests forwarded on
+pub(crate) async fn dispatch<ReqId, Target, Req, Resp, Ctx>(
+    mux: &mut Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+    incoming: &mut mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>, id: ReqId, target: Target,
+    msg: Muxed<Req, Resp>, ctx: Ctx,
+) -> Result<(), Error>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    if let Some((addr, req)) = mux.handle(id.clone(), target, msg)? {
+        if incoming.send((id, addr, req, ctx)).await.is_err() {
+            return Err(Error::Closed);
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl<ReqId, Target, Req, Resp, Ctx> Connector<ReqId, Target, Req, Resp, Error, Ctx>
+    for StreamConnector<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    async fn request(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, req: Req,
+    ) -> Result<Resp, Error> {
+        self.mux.request(ctx, req_id, target, req).await
+    }
+
+    async fn respond(
+        &mut self, ctx: Ctx, req_id: ReqId, target: Target, resp: Resp,
+    ) -> Result<(), Error> {
+        self.mux.respond(ctx, req_id, target, resp).await
+    }
+}
+
+// Stream implementation to allow polling for incoming requests
+impl<ReqId, Target, Req, Resp, Ctx> Stream for StreamConnector<ReqId, Target, Req, Resp, Ctx> {
+    type Item = Incoming<ReqId, Target, Req, Ctx>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.incoming.lock().unwrap().poll_next_unpin(cx)
+    }
+}
+
+/// StreamDriver runs the read and write loops connecting a byte stream to a StreamConnector
+pub struct StreamDriver<ReqId, Target, Req, Resp, Ctx, S, C, F> {
+    mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+    incoming: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>,
+
+    stream: S,
+    codec: C,
+    framing: F,
+
+    target: Target,
+    ctx: Ctx,
+    keepalive: Option<Keepalive<ReqId, Req>>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx, S, C, F> StreamDriver<ReqId, Target, Req, Resp, Ctx, S, C, F>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+    S: AsyncRead + AsyncWrite + Send + 'static,
+    C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+    F: Framing + Clone + Send + 'static,
+{
+    /// Fetch the context passed with incoming requests, for TLS connections this describes the verified peer
+    pub fn ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    /// Enable keepalive probing of the remote peer, tearing down the connection if the peer stops responding
+    pub fn with_keepalive(mut self, keepalive: Keepalive<ReqId, Req>) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Run the connection, resolving when the stream is closed by the remote peer or on error.
+    /// On exit the connector is closed, failing pending and subsequent requests with Error::ConnectionLost
+    pub async fn run(self) -> Result<(), Error> {
+        let (reader, writer) = self.stream.split();
+        let mux = self.mux.clone();
+        let (target, ctx) = (self.target.clone(), self.ctx.clone());
+        let keepalive = self.keepalive.map(|k| k.run(mux.clone(), target, ctx));
+
+        let tx = Self::write(self.mux.clone(), writer, self.codec.clone(), self.framing.clone());
+        let rx = Self::read(self.mux, self.incoming, reader, self.codec, self.framing, self.target, self.ctx);
+
+        futures::pin_mut!(tx, rx);
+
+        let io = async move { future::select(tx, rx).await.factor_first().0 };
+        let hb = async move {
+            match keepalive {
+                Some(k) => Err(k.await),
+                None => future::pending().await,
+            }
+        };
+
+        futures::pin_mut!(io, hb);
+
+        let res = future::select(io, hb).await.factor_first().0;
+
+        mux.close(|| Error::ConnectionLost);
+
+        res
+    }
+
+    async fn write<W: AsyncWrite + Unpin>(
+        mut mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>, mut writer: W, codec: C, framing: F,
+    ) -> Result<(), Error> {
+        let mut buff = vec![];
+
+        while let Some((id, _target, msg, _ctx)) = mux.next().await {
+            let data = codec.encode(&id, &msg).map_err(|e| Error::Codec(format!("{:?}", e)))?;
+
+            buff.clear();
+            framing.encode(&data, &mut buff)?;
+
+            writer.write_all(&buff).await?;
+            writer.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn read<R: AsyncRead + Unpin>(
+        mut mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+        mut incoming: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>, mut reader: R, codec: C,
+        framing: F, target: Target, ctx: Ctx,
+    ) -> Result<(), Error> {
+        let mut buff = vec![];
+        let mut chunk = [0u8; 1024];
+
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                debug!("Stream to {:?} closed", target);
+                return Ok(());
+            }
+
+            buff.extend_from_slice(&chunk[..n]);
+
+            while let Some(frame) = framing.decode(&mut buff)? {
+                // Frames are delimited independently, so a message that fails to decode is skipped
+                let (id, msg) = match codec.decode(&frame) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        warn!("Dropping undecodable message from {:?}: {:?}", target, e);
+                        continue;
+                    }
+                };
+
+                dispatch(&mut mux, &mut incoming, id, target.clone(), msg, ctx.clone()).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::framing::{Cobs, LengthPrefix, Newline};
+
+    type Conn = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, ()>;
+
+    async fn pair<F: Framing + Clone + Send + 'static>(framing: F) -> (Conn, Conn) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accept = task::spawn(async move { listener.accept().await.unwrap().0 });
+        let client = TcpStream::connect(addr).await.unwrap();
+        let server = accept.await;
+
+        let (a, da) = StreamConnector::new(client, EnvelopeCodec, framing.clone(), 0x22, ());
+        let (b, db) = StreamConnector::new(server, EnvelopeCodec, framing, 0x11, ());
+
+        task::spawn(da.run());
+        task::spawn(db.run());
+
+        (a, b)
+    }
+
+    fn check<F: Framing + Clone + Send + 'static>(framing: F) {
+        task::block_on(async {
+            let (mut a, mut b) = pair(framing).await;
+
+            // b reverses incoming requests
+            task::spawn(async move {
+                while let Some((id, from, mut req, _ctx)) = b.next().await {
+                    assert_eq!(from, 0x11);
+                    req.reverse();
+                    b.respond((), id, from, req).await.unwrap();
+                }
+            });
+
+            let resp = a.request((), 1, 0x22, vec![1, 2, 3]).await.unwrap();
+            assert_eq!(resp, vec![3, 2, 1]);
+
+            let resp = a.request((), 2, 0x22, vec![4, 5]).await.unwrap();
+            assert_eq!(resp, vec![5, 4]);
+        });
+    }
+
+    #[test]
+    fn test_stream_skip_invalid() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let accept = task::spawn(async move { listener.accept().await.unwrap().0 });
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let server = accept.await;
+
+            let (mut b, db): (Conn, _) = StreamConnector::new(server, EnvelopeCodec, LengthPrefix::new(), 0x11, ());
+            task::spawn(db.run());
+
+            // A frame too short to hold an envelope, followed by a valid request
+            let framing = LengthPrefix::new();
+            let req: Muxed<Vec<u8>, Vec<u8>> = Muxed::Request(vec![1, 2]);
+            let mut buff = vec![];
+            framing.encode(&[0xff], &mut buff).unwrap();
+            framing.encode(&EnvelopeCodec.encode(&3u16, &req).unwrap(), &mut buff).unwrap();
+            client.write_all(&buff).await.unwrap();
+
+            let (id, from, req, _ctx) = b.next().await.unwrap();
+            assert_eq!((id, from, req), (3, 0x11, vec![1, 2]));
+            assert!(!b.is_closed());
+        });
+    }
+
+    #[test]
+    fn test_stream_length_prefix() {
+        check(LengthPrefix::new());
+    }
+
+    #[test]
+    fn test_stream_cobs() {
+        check(Cobs::new());
+    }
+
+    #[test]
+    fn test_stream_newline() {
+        // Newline framing requires payloads without newlines
+        check(Newline::new());
+    }
+}
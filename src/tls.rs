@@ -0,0 +1,249 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+pub use futures_rustls;
+use futures_rustls::pki_types::{CertificateDer, ServerName};
+use futures_rustls::{client, server};
+pub use futures_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::codec::Codec;
+use crate::framing::Framing;
+use crate::stream::{Error, StreamConnector, StreamDriver};
+
+/// Peer describes the remote end of a TLS connection, as established during the handshake.
+/// This is passed as the Ctx for incoming requests so handlers may authorize requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    /// Server name dialed by the client, or requested by the client via SNI
+    pub server_name: Option<String>,
+    /// Certificate chain presented and verified during the handshake, end-entity first.
+    /// This is empty where the peer was not required to authenticate
+    pub certificates: Vec<CertificateDer<'static>>,
+    /// Application protocol negotiated using ALPN
+    pub alpn: Option<Vec<u8>>,
+}
+
+impl Peer {
+    /// Fetch the verified end-entity certificate of the peer
+    pub fn certificate(&self) -> Option<&CertificateDer<'static>> {
+        self.certificates.first()
+    }
+}
+
+/// Establish a client TLS connection over the provided stream, returning the encrypted stream and verified peer
+pub async fn connect<S>(
+    connector: &TlsConnector, domain: ServerName<'static>, stream: S,
+) -> Result<(client::TlsStream<S>, Peer), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let server_name = domain.to_str().to_string();
+    let stream = connector.connect(domain, stream).await?;
+
+    let (_, conn) = stream.get_ref();
+    let peer = Peer {
+        server_name: Some(server_name),
+        certificates: conn.peer_certificates().map(|c| c.to_vec()).unwrap_or_default(),
+        alpn: conn.alpn_protocol().map(|p| p.to_vec()),
+    };
+
+    debug!("TLS connection established to {:?}", peer.server_name);
+
+    Ok((stream, peer))
+}
+
+/// Accept a server TLS connection over the provided stream, returning the encrypted stream and verified peer
+pub async fn accept<S>(acceptor: &TlsAcceptor, stream: S) -> Result<(server::TlsStream<S>, Peer), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = acceptor.accept(stream).await?;
+
+    let (_, conn) = stream.get_ref();
+    let peer = Peer {
+        server_name: conn.server_name().map(|n| n.to_string()),
+        certificates: conn.peer_certificates().map(|c| c.to_vec()).unwrap_or_default(),
+        alpn: conn.alpn_protocol().map(|p| p.to_vec()),
+    };
+
+    debug!("TLS connection accepted (server name: {:?})", peer.server_name);
+
+    Ok((stream, peer))
+}
+
+impl<ReqId, Target, Req, Resp> StreamConnector<ReqId, Target, Req, Resp, Peer>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+{
+    /// Create a new connector over a client TLS connection established using the provided stream.
+    /// The verified server identity is passed as the Ctx with each incoming request.
+    #[allow(clippy::type_complexity)]
+    pub async fn tls_connect<S, C, F>(
+        connector: &TlsConnector, domain: ServerName<'static>, stream: S, codec: C, framing: F, target: Target,
+    ) -> Result<(Self, StreamDriver<ReqId, Target, Req, Resp, Peer, client::TlsStream<S>, C, F>), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+        F: Framing + Clone + Send + 'static,
+    {
+        let (stream, peer) = connect(connector, domain, stream).await?;
+
+        Ok(Self::new(stream, codec, framing, target, peer))
+    }
+
+    /// Create a new connector over a server TLS connection accepted using the provided stream.
+    /// The verified client identity (where client authentication is configured) is passed as the Ctx
+    /// with each incoming request.
+    #[allow(clippy::type_complexity)]
+    pub async fn tls_accept<S, C, F>(
+        acceptor: &TlsAcceptor, stream: S, codec: C, framing: F, target: Target,
+    ) -> Result<(Self, StreamDriver<ReqId, Target, Req, Resp, Peer, server::TlsStream<S>, C, F>), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+        F: Framing + Clone + Send + 'static,
+    {
+        let (stream, peer) = accept(acceptor, stream).await?;
+
+        Ok(Self::new(stream, codec, framing, target, peer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use futures::prelude::*;
+    use futures_rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use futures_rustls::rustls::server::WebPkiClientVerifier;
+    use futures_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::connector::Connector;
+    use crate::framing::LengthPrefix;
+
+    type Conn = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, Peer>;
+
+    struct Identity {
+        cert: CertificateDer<'static>,
+        key: PrivateKeyDer<'static>,
+    }
+
+    // Generate a self signed identity for the provided name
+    fn identity(name: &str) -> Identity {
+        let CertifiedKey { cert, key_pair } = generate_simple_self_signed(vec![name.to_string()]).unwrap();
+
+        Identity {
+            cert: cert.der().clone(),
+            key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der())),
+        }
+    }
+
+    fn roots(trusted: &CertificateDer<'static>) -> Arc<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(trusted.clone()).unwrap();
+        Arc::new(roots)
+    }
+
+    // Start a server requiring client authentication, responding to requests with the client certificate length
+    async fn server(server: Identity, client: &CertificateDer<'static>) -> SocketAddr {
+        let verifier = WebPkiClientVerifier::builder(roots(client)).build().unwrap();
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(vec![server.cert], server.key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        task::spawn(async move {
+            while let Ok((s, _)) = listener.accept().await {
+                let (mut conn, driver): (Conn, _) =
+                    match StreamConnector::tls_accept(&acceptor, s, EnvelopeCodec, LengthPrefix::new(), 0x11).await {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    };
+                task::spawn(driver.run());
+
+                task::spawn(async move {
+                    while let Some((id, from, _req, peer)) = conn.next().await {
+                        let len = peer.certificate().map(|c| c.len()).unwrap_or(0) as u16;
+                        conn.respond(peer, id, from, len.to_be_bytes().to_vec()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    fn client(client: Identity, server: &CertificateDer<'static>) -> TlsConnector {
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots(server))
+            .with_client_auth_cert(vec![client.cert], client.key)
+            .unwrap();
+
+        TlsConnector::from(Arc::new(config))
+    }
+
+    #[test]
+    fn test_tls_connector() {
+        task::block_on(async {
+            let (s, c) = (identity("localhost"), identity("client"));
+            let (server_cert, client_cert) = (s.cert.clone(), c.cert.clone());
+
+            let addr = server(s, &client_cert).await;
+            let connector = client(c, &server_cert);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let domain = ServerName::try_from("localhost").unwrap();
+            let (mut conn, driver): (Conn, _) =
+                StreamConnector::tls_connect(&connector, domain, stream, EnvelopeCodec, LengthPrefix::new(), 0x22)
+                    .await
+                    .unwrap();
+
+            let peer = driver.ctx().clone();
+            task::spawn(driver.run());
+
+            // The server identity is verified
+            assert_eq!(peer.server_name.as_deref(), Some("localhost"));
+            assert_eq!(peer.certificate(), Some(&server_cert));
+
+            // And the client identity is available to the server handler
+            let resp = conn.request(peer, 1, 0x22, vec![1, 2, 3]).await.unwrap();
+            assert_eq!(resp, (client_cert.len() as u16).to_be_bytes().to_vec());
+        });
+    }
+
+    #[test]
+    fn test_tls_untrusted() {
+        task::block_on(async {
+            let (s, c) = (identity("localhost"), identity("client"));
+
+            let addr = server(s, &c.cert.clone()).await;
+
+            // Clients not trusting the server certificate fail to connect
+            let connector = client(c, &identity("localhost").cert);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let domain = ServerName::try_from("localhost").unwrap();
+            let res: Result<(Conn, _), _> =
+                StreamConnector::tls_connect(&connector, domain, stream, EnvelopeCodec, LengthPrefix::new(), 0x22)
+                    .await;
+
+            assert!(matches!(res, Err(Error::Io(_))));
+        });
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use futures::channel::mpsc;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::prelude::*;
+
+pub use async_tungstenite;
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+
+use crate::codec::Codec;
+use crate::keepalive::Keepalive;
+use crate::mux::Mux;
+use crate::stream::{dispatch, Error, Incoming, StreamConnector};
+
+impl From<async_tungstenite::tungstenite::Error> for Error {
+    fn from(e: async_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(e)
+    }
+}
+
+impl<ReqId, Target, Req, Resp, Ctx> StreamConnector<ReqId, Target, Req, Resp, Ctx>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+{
+    /// Create a new connector over an established WebSocket connection, with each WebSocket message
+    /// carrying one encoded envelope. Target identifies the remote peer and Ctx is passed with each incoming request.
+    /// This returns the connector and a driver that must be polled (or spawned) to run the connection.
+    #[allow(clippy::type_complexity)]
+    pub fn websocket<S, C>(
+        ws: WebSocketStream<S>, codec: C, target: Target, ctx: Ctx,
+    ) -> (Self, WebSocketDriver<ReqId, Target, Req, Resp, Ctx, S, C>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+    {
+        let (conn, mux, tx) = Self::unbound();
+
+        let driver = WebSocketDriver {
+            mux,
+            incoming: tx,
+            ws,
+            codec,
+            target,
+            ctx,
+            keepalive: None,
+            text: false,
+        };
+
+        (conn, driver)
+    }
+}
+
+/// WebSocketDriver runs the read and write loops connecting a WebSocket to a StreamConnector.
+/// Pings from the remote peer are answered automatically
+pub struct WebSocketDriver<ReqId, Target, Req, Resp, Ctx, S, C> {
+    mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+    incoming: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>,
+
+    ws: WebSocketStream<S>,
+    codec: C,
+
+    target: Target,
+    ctx: Ctx,
+    text: bool,
+    keepalive: Option<Keepalive<ReqId, Req>>,
+}
+
+impl<ReqId, Target, Req, Resp, Ctx, S, C> WebSocketDriver<ReqId, Target, Req, Resp, Ctx, S, C>
+where
+    ReqId: Eq + Hash + Debug + Clone + Send + 'static,
+    Target: Debug + Clone + Send + 'static,
+    Req: Debug + Send + 'static,
+    Resp: Debug + Send + 'static,
+    Ctx: Debug + Clone + Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Codec<ReqId, Req, Resp> + Clone + Send + 'static,
+{
+    /// Send outgoing envelopes as text rather than binary messages, for use with text based codecs
+    pub fn with_text(mut self) -> Self {
+        self.text = true;
+        self
+    }
+
+    /// Enable keepalive probing of the remote peer, tearing down the connection if the peer stops responding
+    pub fn with_keepalive(mut self, keepalive: Keepalive<ReqId, Req>) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Run the connection, resolving when the WebSocket is closed by the remote peer or on error.
+    /// On exit the connector is closed, failing pending and subsequent requests with Error::ConnectionLost
+    pub async fn run(self) -> Result<(), Error> {
+        let (sink, stream) = self.ws.split();
+        let mux = self.mux.clone();
+        let (target, ctx) = (self.target.clone(), self.ctx.clone());
+        let keepalive = self.keepalive.map(|k| k.run(mux.clone(), target, ctx));
+
+        let tx = Self::write(self.mux.clone(), sink, self.codec.clone(), self.text);
+        let rx = Self::read(self.mux, self.incoming, stream, self.codec, self.target, self.ctx);
+
+        futures::pin_mut!(tx, rx);
+
+        let io = async move { future::select(tx, rx).await.factor_first().0 };
+        let hb = async move {
+            match keepalive {
+                Some(k) => Err(k.await),
+                None => future::pending().await,
+            }
+        };
+
+        futures::pin_mut!(io, hb);
+
+        let res = future::select(io, hb).await.factor_first().0;
+
+        mux.close(|| Error::ConnectionLost);
+
+        res
+    }
+
+    async fn write<W>(
+        mut mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>, mut sink: W, codec: C, text: bool,
+    ) -> Result<(), Error>
+    where
+        W: Sink<Message, Error = async_tungstenite::tungstenite::Error> + Unpin,
+    {
+        while let Some((id, _target, msg, _ctx)) = mux.next().await {
+            let data = codec.encode(&id, &msg).map_err(|e| Error::Codec(format!("{:?}", e)))?;
+
+            let m = match text {
+                true => Message::text(String::from_utf8(data).map_err(|e| Error::Codec(format!("{:?}", e)))?),
+                false => Message::binary(data),
+            };
+
+            sink.send(m).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn read<R>(
+        mut mux: Mux<ReqId, Target, Req, Resp, Error, Ctx>,
+        mut incoming: mpsc::Sender<Incoming<ReqId, Target, Req, Ctx>>, mut stream: R, codec: C,
+        target: Target, ctx: Ctx,
+    ) -> Result<(), Error>
+    where
+        R: Stream<Item = Result<Message, async_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        while let Some(m) = stream.next().await {
+            let data = match m? {
+                Message::Binary(b) => b,
+                Message::Text(t) => t.into(),
+                Message::Close(_) => break,
+                // Pings are answered by the underlying WebSocket, other control frames are ignored
+                _ => continue,
+            };
+
+            // Messages are delimited independently, so one that fails to decode is skipped
+            let (id, msg) = match codec.decode(&data) {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!("Dropping undecodable message from {:?}: {:?}", target, e);
+                    continue;
+                }
+            };
+
+            dispatch(&mut mux, &mut incoming, id, target.clone(), msg, ctx.clone()).await?;
+        }
+
+        debug!("WebSocket to {:?} closed", target);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::task;
+    use async_tungstenite::{accept_async, client_async};
+
+    use super::*;
+    use crate::codec::EnvelopeCodec;
+    use crate::connector::Connector;
+    use crate::muxed::Muxed;
+
+    type Conn = StreamConnector<u16, u32, Vec<u8>, Vec<u8>, ()>;
+
+    // Start a loopback server, reversing incoming requests
+    async fn server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        task::spawn(async move {
+            let (s, _) = listener.accept().await.unwrap();
+            let ws = accept_async(s).await.unwrap();
+
+            let (mut conn, driver): (Conn, _) = StreamConnector::websocket(ws, EnvelopeCodec, 0x11, ());
+            task::spawn(driver.run());
+
+            while let Some((id, from, mut req, _ctx)) = conn.next().await {
+                req.reverse();
+                conn.respond((), id, from, req).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_websocket_connector() {
+        task::block_on(async {
+            let addr = server().await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (ws, _) = client_async("ws://localhost/", s).await.unwrap();
+
+            let (mut conn, driver): (Conn, _) = StreamConnector::websocket(ws, EnvelopeCodec, 0x22, ());
+            task::spawn(driver.run());
+
+            let resp = conn.request((), 1, 0x22, vec![1, 2, 3]).await.unwrap();
+            assert_eq!(resp, vec![3, 2, 1]);
+        });
+    }
+
+    #[test]
+    fn test_websocket_ping() {
+        task::block_on(async {
+            let addr = server().await;
+
+            let s = TcpStream::connect(addr).await.unwrap();
+            let (mut ws, _) = client_async("ws://localhost/", s).await.unwrap();
+
+            // Pings are answered transparently
+            ws.send(Message::Ping(vec![0xaa].into())).await.unwrap();
+            match ws.next().await.unwrap().unwrap() {
+                Message::Pong(p) => assert_eq!(&p[..], &[0xaa]),
+                m => panic!("unexpected message: {:?}", m),
+            }
+
+            // And do not interrupt request handling
+            let req = EnvelopeCodec.encode(&7u16, &Muxed::<Vec<u8>, Vec<u8>>::Request(vec![4, 5])).unwrap();
+            ws.send(Message::binary(req)).await.unwrap();
+
+            let resp = ws.next().await.unwrap().unwrap().into_data();
+            let resp: (u16, Muxed<Vec<u8>, Vec<u8>>) = EnvelopeCodec.decode(&resp).unwrap();
+            assert_eq!(resp, (7, Muxed::Response(vec![5, 4])));
+        });
+    }
+}
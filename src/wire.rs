@@ -1,24 +1,172 @@
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::hash::Hash;
 use std::sync::{Arc, Mutex};
 use std::clone::Clone;
 use std::fmt::Debug;
 use std::pin::Pin;
+use std::time::Duration;
 
 use futures::prelude::*;
 use futures::channel::{mpsc, oneshot};
 use futures::task::{Context, Poll};
 use async_trait::async_trait;
+#[cfg(feature = "faults")]
+use rand::{Rng, SeedableRng, rngs::StdRng};
 
+#[cfg(feature = "faults")]
+use crate::clock::Jitter;
+use crate::clock::{Clock, SystemClock};
 use crate::connector::Connector;
 
-/// Wire provides an interconnect to support integration testing of Mux based implementations
+type Connectors<ReqId, Target, Req, Resp, E, Ctx> = Arc<Mutex<HashMap<Target, WireMux<ReqId, Target, Req, Resp, E, Ctx>>>>;
+type Pending<ReqId, Target, Resp, E> = Arc<Mutex<HashMap<(Target, Target, ReqId), oneshot::Sender<Result<(Resp, Duration), E>>>>>;
+type Unreachable<Target, E> = Arc<dyn Fn(&Target, &Target) -> E + Send + Sync>;
+
+/// Link configures faults applied to messages sent from one target to another.
+/// Links are directional, so both directions must be configured for symmetric faults.
+/// Random faults (loss, duplication, reordering and jitter) require the `faults` feature
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Probability of a message being dropped
+    #[cfg(feature = "faults")]
+    pub loss: f64,
+    /// Probability of a request being delivered twice. Responses are returned directly to the waiting
+    /// request so are never duplicated
+    #[cfg(feature = "faults")]
+    pub duplicate: f64,
+    /// Probability of a message being held for the reorder delay, allowing later messages to overtake it
+    #[cfg(feature = "faults")]
+    pub reorder: f64,
+    /// Additional delay applied to reordered messages
+    #[cfg(feature = "faults")]
+    pub reorder_delay: Duration,
+    /// Fixed delay applied to all messages
+    pub latency: Duration,
+    /// Random variation added to the latency of each message
+    #[cfg(feature = "faults")]
+    pub jitter: Jitter,
+    /// Partitioned links drop all messages
+    pub partitioned: bool,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Link {
+            #[cfg(feature = "faults")]
+            loss: 0.0,
+            #[cfg(feature = "faults")]
+            duplicate: 0.0,
+            #[cfg(feature = "faults")]
+            reorder: 0.0,
+            #[cfg(feature = "faults")]
+            reorder_delay: Duration::from_millis(100),
+            latency: Duration::from_secs(0),
+            #[cfg(feature = "faults")]
+            jitter: Jitter::None,
+            partitioned: false,
+        }
+    }
+}
+
+impl Link {
+    // Sample the delivery of a message sent over the link
+    #[cfg(feature = "faults")]
+    fn sample<R: Rng>(&self, rng: &mut R) -> Delivery {
+        if self.partitioned || rng.gen_bool(self.loss) {
+            return Delivery{ copies: 0, delay: Duration::from_secs(0) };
+        }
+
+        let copies = if rng.gen_bool(self.duplicate) { 2 } else { 1 };
+
+        let mut delay = self.latency + self.jitter.sample(rng);
+        if rng.gen_bool(self.reorder) {
+            delay += self.reorder_delay;
+        }
+
+        Delivery{ copies, delay }
+    }
+
+    #[cfg(not(feature = "faults"))]
+    fn sample(&self) -> Delivery {
+        match self.partitioned {
+            true => Delivery{ copies: 0, delay: Duration::from_secs(0) },
+            false => Delivery{ copies: 1, delay: self.latency },
+        }
+    }
+}
+
+/// Delivery describes the fate of a message sampled from a link
+struct Delivery {
+    copies: usize,
+    delay: Duration,
+}
+
+/// Faults holds link configurations, the current topology and the random source used to apply them
+struct Faults<Target> {
+    default: Link,
+    links: HashMap<(Target, Target), Link>,
+    cut: HashSet<(Target, Target)>,
+    isolated: HashSet<Target>,
+    #[cfg(feature = "faults")]
+    rng: StdRng,
+    clock: Arc<dyn Clock>,
+}
+
+impl <Target> Faults<Target>
+where
+    Target: Clone + Hash + Eq,
+{
+    // Check whether a message may be sent from one target to another
+    fn reachable(&self, from: &Target, to: &Target) -> bool {
+        !self.isolated.contains(from) && !self.isolated.contains(to) && !self.cut.contains(&(from.clone(), to.clone()))
+    }
+
+    // Sample the delivery of a message sent over a link
+    fn sample(&mut self, from: Target, to: Target) -> Delivery {
+        let link = self.links.get(&(from, to)).unwrap_or(&self.default);
+
+        #[cfg(feature = "faults")]
+        return link.sample(&mut self.rng);
+
+        #[cfg(not(feature = "faults"))]
+        link.sample()
+    }
+}
+
+/// Waiting removes a pending request when the request is lost or dropped,
+/// so that unanswered requests do not leave response channels behind
+struct Waiting<ReqId: Hash + Eq, Target: Hash + Eq, Resp, E> {
+    key: (Target, Target, ReqId),
+    requests: Pending<ReqId, Target, Resp, E>,
+}
+
+impl <ReqId: Hash + Eq, Target: Hash + Eq, Resp, E> Drop for Waiting<ReqId, Target, Resp, E> {
+    fn drop(&mut self) {
+        self.requests.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Wire provides an interconnect to support integration testing of Mux based implementations.
+///
+/// Messages are delivered perfectly by default, faults may be configured per link to simulate latency
+/// and partitions, along with loss, duplication and reordering with the `faults` feature.
+///
+/// Partitions and cuts both stop messages between targets, but fail differently:
+/// - Partitions are link faults, silently dropping messages as for loss. Lost messages are never delivered,
+///   so requests over a partitioned or lossy link should be bounded by a timeout.
+/// - Cuts and isolation change the topology, modelling network splits. Requests over cut links or to unknown
+///   targets fail immediately with the unreachable error provided to `Wire::new`, as do requests whose response
+///   is sent over a cut link.
 pub struct Wire <ReqId, Target, Req, Resp, E, Ctx> {
-    connectors: Arc<Mutex<HashMap<Target, WireMux<ReqId, Target, Req, Resp, E, Ctx>>>>,
+    connectors: Connectors<ReqId, Target, Req, Resp, E, Ctx>,
+
+    requests: Pending<ReqId, Target, Resp, E>,
+
+    faults: Arc<Mutex<Faults<Target>>>,
 
-    requests: Arc<Mutex<HashMap<(Target, Target, ReqId), oneshot::Sender<Resp>>>>,
+    unreachable: Unreachable<Target, E>,
 
     _e: PhantomData<E>, 
     _ctx: PhantomData<Ctx>,
@@ -27,8 +175,8 @@ pub struct Wire <ReqId, Target, Req, Resp, E, Ctx> {
 impl <ReqId, Target, Req, Resp, E, Ctx> Clone for Wire<ReqId, Target, Req, Resp, E, Ctx>
 where
     ReqId: Clone + Hash + Eq + PartialEq + Debug + Send + 'static,
-    Target: Clone + Hash + PartialEq + Eq + Send + 'static,
-    Req: PartialEq + Debug + Send + 'static,
+    Target: Clone + Hash + PartialEq + Eq + Debug + Send + 'static,
+    Req: Clone + PartialEq + Debug + Send + 'static,
     Resp: PartialEq + Debug + Send + 'static,
     E: PartialEq + Debug + Send + 'static,
     Ctx: Clone + PartialEq + Debug + Send + 'static,
@@ -37,6 +185,8 @@ where
         Wire {
             connectors: self.connectors.clone(),
             requests: self.requests.clone(),
+            faults: self.faults.clone(),
+            unreachable: self.unreachable.clone(),
 
             _e: PhantomData,
             _ctx: PhantomData,
@@ -47,23 +197,116 @@ where
 impl <ReqId, Target, Req, Resp, E, Ctx> Wire<ReqId, Target, Req, Resp, E, Ctx> 
 where
     ReqId: Clone + Hash + Eq + PartialEq + Debug + Send + 'static,
-    Target: Clone + Hash + PartialEq + Eq + Send + 'static,
-    Req: PartialEq + Debug + Send + 'static,
+    Target: Clone + Hash + PartialEq + Eq + Debug + Send + 'static,
+    Req: Clone + PartialEq + Debug + Send + 'static,
     Resp: PartialEq + Debug + Send + 'static,
     E: PartialEq + Debug + Send + 'static,
     Ctx: Clone + PartialEq + Debug + Send + 'static,
 {
-    /// Create a new Wire interconnect
-    pub fn new() -> Wire<ReqId, Target, Req, Resp, E, Ctx>  {
+    /// Create a new Wire interconnect, failing messages sent over cut links or to unknown targets with errors
+    /// created from the source and destination of the message by the provided function
+    pub fn new<F>(unreachable: F) -> Wire<ReqId, Target, Req, Resp, E, Ctx>
+    where
+        F: Fn(&Target, &Target) -> E + Send + Sync + 'static,
+    {
         Wire{
             connectors: Arc::new(Mutex::new(HashMap::new())),
             requests: Arc::new(Mutex::new(HashMap::new())),
+            faults: Arc::new(Mutex::new(Faults{
+                default: Link::default(),
+                links: HashMap::new(),
+                cut: HashSet::new(),
+                isolated: HashSet::new(),
+                #[cfg(feature = "faults")]
+                rng: StdRng::from_entropy(),
+                clock: Arc::new(SystemClock),
+            })),
+            unreachable: Arc::new(unreachable),
 
             _e: PhantomData,
             _ctx: PhantomData,
         }
     }
 
+    /// Seed the random number generator used to apply faults, making runs reproducible
+    #[cfg(feature = "faults")]
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.faults.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Set the clock used to apply message delays
+    pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.faults.lock().unwrap().clock = Arc::new(clock);
+        self
+    }
+
+    /// Set the link configuration used between targets without a specific configuration
+    pub fn with_default_link(self, link: Link) -> Self {
+        self.faults.lock().unwrap().default = link;
+        self
+    }
+
+    /// Set the configuration of the link from one target to another, this may be changed at runtime
+    pub fn set_link(&self, from: Target, to: Target, link: Link) {
+        self.faults.lock().unwrap().links.insert((from, to), link);
+    }
+
+    /// Partition or rejoin a pair of targets, applying to both directions of the link.
+    /// See the Wire documentation for the difference between partitions and cuts
+    pub fn set_partitioned(&self, a: Target, b: Target, partitioned: bool) {
+        let mut f = self.faults.lock().unwrap();
+        let default = f.default.clone();
+
+        for key in [(a.clone(), b.clone()), (b, a)] {
+            f.links.entry(key).or_insert_with(|| default.clone()).partitioned = partitioned;
+        }
+    }
+
+    /// Cut the link between a pair of targets in both directions
+    pub fn cut(&self, a: Target, b: Target) {
+        self.cut_one_way(a.clone(), b.clone());
+        self.cut_one_way(b, a);
+    }
+
+    /// Cut the link from one target to another, leaving the reverse direction intact
+    pub fn cut_one_way(&self, from: Target, to: Target) {
+        self.faults.lock().unwrap().cut.insert((from, to));
+    }
+
+    /// Restore the link between a pair of targets in both directions
+    pub fn restore(&self, a: Target, b: Target) {
+        self.restore_one_way(a.clone(), b.clone());
+        self.restore_one_way(b, a);
+    }
+
+    /// Restore the link from one target to another
+    pub fn restore_one_way(&self, from: Target, to: Target) {
+        self.faults.lock().unwrap().cut.remove(&(from, to));
+    }
+
+    /// Isolate a target, cutting all links to and from it until it is rejoined
+    pub fn isolate(&self, target: Target) {
+        self.faults.lock().unwrap().isolated.insert(target);
+    }
+
+    /// Rejoin an isolated target, links cut individually remain cut
+    pub fn rejoin(&self, target: Target) {
+        self.faults.lock().unwrap().isolated.remove(&target);
+    }
+
+    /// Restore all cut links and rejoin all isolated targets
+    pub fn heal(&self) {
+        let mut f = self.faults.lock().unwrap();
+        f.cut.clear();
+        f.isolated.clear();
+    }
+
+    /// Check whether messages may currently be sent from one target to another
+    pub fn is_reachable(&self, from: &Target, to: &Target) -> bool {
+        self.connectors.lock().unwrap().contains_key(to) && self.faults.lock().unwrap().reachable(from, to)
+    }
+
     /// Create a new connector for the provided target address
     pub fn connector(&mut self, target: Target) -> WireMux<ReqId, Target, Req, Resp, E, Ctx> {
         let w = WireMux::new(self.clone(), target.clone());
@@ -73,30 +316,85 @@ where
         w
     }
 
-    async fn request(&mut self, _ctx: Ctx, to: Target, from: Target, id: ReqId, req: Req) -> Result<Resp, ()> {
-        // Fetch matching connector
-        let mut conn = {
-            let c = self.connectors.lock().unwrap();
-            c.get(&to.clone()).unwrap().clone()
+    // Sample the delivery of a message, returning the delivery and clock
+    fn sample(&self, from: &Target, to: &Target) -> (Delivery, Arc<dyn Clock>) {
+        let mut f = self.faults.lock().unwrap();
+        let d = f.sample(from.clone(), to.clone());
+        (d, f.clock.clone())
+    }
+
+    async fn request(&mut self, _ctx: Ctx, to: Target, from: Target, id: ReqId, req: Req) -> Result<Resp, E> {
+        // Fetch matching connector where the destination is reachable
+        let conn = self.connectors.lock().unwrap().get(&to).cloned();
+        let mut conn = match conn {
+            Some(c) if self.faults.lock().unwrap().reachable(&from, &to) => c,
+            _ => {
+                debug!("Request id: {:?} from {:?} to {:?} unreachable", id, from, to);
+                return Err((self.unreachable)(&from, &to))
+            },
         };
 
-        // Bind response channel
+        // Bind response channel, removed when the request is lost or dropped
         let (tx, rx) = oneshot::channel();
-        self.requests.lock().unwrap().insert((to, from.clone(), id.clone()), tx);
+        let key = (to.clone(), from.clone(), id.clone());
+        self.requests.lock().unwrap().insert(key.clone(), tx);
+        let waiting = Waiting{ key, requests: self.requests.clone() };
+
+        // Forward request, applying link faults
+        let (delivery, clock) = self.sample(&from, &to);
+        if delivery.copies == 0 {
+            debug!("Request id: {:?} from {:?} to {:?} dropped", id, from, to);
+            drop(waiting);
+            return future::pending().await
+        }
 
-        // Forward request
-        conn.send(from, id, req).await.unwrap();
+        if delivery.delay > Duration::from_secs(0) {
+            clock.sleep(delivery.delay).await;
+        }
 
-        // Await response
-        let res = rx.await.unwrap();
+        for _ in 0..delivery.copies {
+            conn.send(from.clone(), id.clone(), req.clone()).await.unwrap();
+        }
+
+        // Await response, lost responses are never answered
+        let (res, delay) = match rx.await {
+            Ok(Ok(r)) => r,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => future::pending().await,
+        };
+        drop(waiting);
+
+        if delay > Duration::from_secs(0) {
+            clock.sleep(delay).await;
+        }
 
         Ok(res)
     }
 
     async fn respond(&mut self, _ctx: Ctx, to: Target, from: Target, id: ReqId, resp: Resp) -> Result<(), E> {
-        let pending = self.requests.lock().unwrap().remove(&(from, to, id)).unwrap();
-        
-        pending.send(resp).unwrap();
+        let pending = match self.requests.lock().unwrap().remove(&(from.clone(), to.clone(), id.clone())) {
+            Some(p) => p,
+            None => {
+                debug!("Response id: {:?} from {:?} to {:?} has no pending request, dropping", id, from, to);
+                return Ok(())
+            },
+        };
+
+        // Responses over cut links fail the waiting request, as with lost responses the responder is not notified
+        if !self.faults.lock().unwrap().reachable(&from, &to) {
+            debug!("Response id: {:?} from {:?} to {:?} unreachable", id, from, to);
+            let _ = pending.send(Err((self.unreachable)(&from, &to)));
+            return Ok(())
+        }
+
+        // Forward response, applying link faults
+        let (delivery, _) = self.sample(&from, &to);
+        if delivery.copies == 0 {
+            debug!("Response id: {:?} from {:?} to {:?} dropped", id, from, to);
+            return Ok(())
+        }
+
+        let _ = pending.send(Ok((resp, delivery.delay)));
         
         Ok(())
     }
@@ -117,8 +415,8 @@ pub struct WireMux<ReqId, Target, Req, Resp, E, Ctx> {
 impl <ReqId, Target, Req, Resp, E, Ctx> WireMux<ReqId, Target, Req, Resp, E, Ctx>
 where
     ReqId: Clone + Hash + Eq + PartialEq + Debug + Send + 'static,
-    Target: Clone + Hash + PartialEq + Eq + Send + 'static,
-    Req: PartialEq + Debug + Send + 'static,
+    Target: Clone + Hash + PartialEq + Eq + Debug + Send + 'static,
+    Req: Clone + PartialEq + Debug + Send + 'static,
     Resp: PartialEq + Debug + Send + 'static,
     E: PartialEq + Debug + Send + 'static,
     Ctx: Clone + PartialEq + Debug + Send + 'static,
@@ -143,7 +441,7 @@ where
         
         match tx.send((from, id, req)).await {
             Ok(_) => (),
-            Err(e) => panic!(e),
+            Err(e) => panic!("{:?}", e),
         };
 
         Ok(())
@@ -154,8 +452,8 @@ where
 impl <ReqId, Target, Req, Resp, E, Ctx> Clone for WireMux<ReqId, Target, Req, Resp, E, Ctx> 
 where
     ReqId: Clone + Hash + Eq + PartialEq + Debug + Send + 'static,
-    Target: Clone + Hash + PartialEq + Eq + Send + 'static,
-    Req: PartialEq + Debug + Send + 'static,
+    Target: Clone + Hash + PartialEq + Eq + Debug + Send + 'static,
+    Req: Clone + PartialEq + Debug + Send + 'static,
     Resp: PartialEq + Debug + Send + 'static,
     E: PartialEq + Debug + Send + 'static,
     Ctx: Clone + PartialEq + Debug + Send + 'static,
@@ -178,8 +476,8 @@ where
 impl <ReqId, Target, Req, Resp, E, Ctx> Connector<ReqId, Target, Req, Resp, E, Ctx> for WireMux <ReqId, Target, Req, Resp, E, Ctx> 
 where
     ReqId: Clone + Hash + Eq + PartialEq + Debug + Send + 'static,
-    Target: Clone + Hash + PartialEq + Eq + Send + 'static,
-    Req: PartialEq + Debug + Send + 'static,
+    Target: Clone + Hash + PartialEq + Eq + Debug + Send + 'static,
+    Req: Clone + PartialEq + Debug + Send + 'static,
     Resp: PartialEq + Debug + Send + 'static,
     E: PartialEq + Debug + Send + 'static,
     Ctx: Clone + PartialEq + Debug + Send + 'static,
@@ -191,12 +489,7 @@ where
         let addr = self.addr.clone();
 
         // Send to connector and await response
-        let res = match self.connector.request(ctx, target, addr, req_id, req).await {
-            Ok(r) => r,
-            Err(e) => panic!(e),
-        };
-
-        Ok(res)
+        self.connector.request(ctx, target, addr, req_id, req).await
     }
 
     // Respond to a received request
@@ -206,12 +499,7 @@ where
         let mut conn = self.connector.clone();
         let addr = self.addr.clone();
 
-        match conn.respond(ctx, target, addr, req_id, resp).await {
-            Ok(_) => (),
-            Err(e) => panic!(e),
-        };
-
-        Ok(())
+        conn.respond(ctx, target, addr, req_id, resp).await
     }
 }
 
@@ -238,13 +526,15 @@ where
 mod tests {
 
     use futures::prelude::*;
-    use futures::executor::block_on;
+    use futures::executor::{block_on, LocalPool};
+    use futures::task::LocalSpawnExt;
 
     use super::*;
+    use crate::clock::VirtualClock;
 
     #[test]
     fn test_wiring() {
-        let mut i: Wire<u16, u64, u32, u32, (), ()> = Wire::new();
+        let mut i: Wire<u16, u64, u32, u32, (), ()> = Wire::new(|_, _| ());
         
         let mut c1 = i.connector(0x11);
         let mut c2 = i.connector(0x22);
@@ -267,4 +557,258 @@ mod tests {
 
     }
 
-}
\ No newline at end of file
+    type TestWire = Wire<u16, u64, u32, u32, String, ()>;
+    type TestMux = WireMux<u16, u64, u32, u32, String, ()>;
+
+    // Sim runs connectors on a single threaded executor against a virtual clock,
+    // so delays and unanswered requests may be checked deterministically
+    struct Sim {
+        pool: LocalPool,
+        clock: VirtualClock,
+    }
+
+    impl Sim {
+        fn new(wire: TestWire) -> (Self, TestWire) {
+            let clock = VirtualClock::new();
+            let wire = wire.with_clock(clock.clone());
+
+            (Sim{ pool: LocalPool::new(), clock }, wire)
+        }
+
+        // Spawn a handler responding to requests, logging the source of each request received
+        fn echo(&self, mut c: TestMux) -> Arc<Mutex<Vec<u64>>> {
+            let log = Arc::new(Mutex::new(vec![]));
+            let l = log.clone();
+
+            self.pool.spawner().spawn_local(async move {
+                while let Some((from, id, val)) = c.next().await {
+                    l.lock().unwrap().push(from);
+
+                    c.respond((), id, from, val + 10).await.unwrap();
+                }
+            }).unwrap();
+
+            log
+        }
+
+        // Start a request, returning a receiver for the result
+        fn start(&mut self, c: &TestMux, id: u16, to: u64) -> oneshot::Receiver<Result<u32, String>> {
+            let mut c = c.clone();
+            let (tx, rx) = oneshot::channel();
+
+            self.pool.spawner().spawn_local(async move {
+                let _ = tx.send(c.request((), id, to, 40).await);
+            }).unwrap();
+            self.pool.run_until_stalled();
+
+            rx
+        }
+
+        // Advance the clock, running until no further progress can be made
+        fn advance(&mut self, d: Duration) {
+            self.clock.advance(d);
+            self.pool.run_until_stalled();
+        }
+
+        // Make a request, returning None where the request remains unanswered
+        fn try_request(&mut self, c: &TestMux, id: u16, to: u64) -> Option<Result<u32, String>> {
+            self.start(c, id, to).try_recv().unwrap()
+        }
+
+        // Make a request expected to succeed, returning None where the request remains unanswered
+        fn request(&mut self, c: &TestMux, id: u16, to: u64) -> Option<u32> {
+            self.try_request(c, id, to).map(|r| r.unwrap())
+        }
+    }
+
+    fn unreachable(from: &u64, to: &u64) -> String {
+        format!("{:x} unreachable from {:x}", to, from)
+    }
+
+    #[test]
+    fn test_wire_latency() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+        let latency = Link{ latency: Duration::from_millis(20), ..Default::default() };
+        i.set_link(0x11, 0x22, latency.clone());
+        i.set_link(0x22, 0x11, latency);
+
+        let c1 = i.connector(0x11);
+        let log = s.echo(i.connector(0x22));
+
+        // Latency applies to both requests and responses
+        let mut rx = s.start(&c1, 1, 0x22);
+        assert!(log.lock().unwrap().is_empty());
+
+        s.advance(Duration::from_millis(20));
+        assert_eq!(*log.lock().unwrap(), vec![0x11]);
+
+        s.advance(Duration::from_millis(19));
+        assert_eq!(rx.try_recv(), Ok(None));
+
+        s.advance(Duration::from_millis(1));
+        assert_eq!(rx.try_recv(), Ok(Some(Ok(50))));
+    }
+
+    #[test]
+    fn test_wire_loss_partition() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+
+        let c1 = i.connector(0x11);
+        let c3 = i.connector(0x33);
+        s.echo(i.connector(0x22));
+
+        // Partitions may be toggled at runtime, with lost requests no longer pending
+        i.set_partitioned(0x11, 0x22, true);
+        assert_eq!(s.request(&c1, 1, 0x22), None);
+        assert!(i.requests.lock().unwrap().is_empty());
+        assert_eq!(s.request(&c3, 2, 0x22), Some(50));
+
+        i.set_partitioned(0x11, 0x22, false);
+        assert_eq!(s.request(&c1, 3, 0x22), Some(50));
+
+        // Lost responses are never delivered
+        #[cfg(feature = "faults")]
+        {
+            i.set_link(0x22, 0x33, Link{ loss: 1.0, ..Default::default() });
+            assert_eq!(s.request(&c3, 4, 0x22), None);
+            assert!(i.requests.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_wire_pending_dropped() {
+        let mut i = TestWire::new(unreachable);
+
+        let mut c1 = i.connector(0x11);
+        let _c2 = i.connector(0x22);
+
+        // Dropped requests are no longer pending
+        block_on(async {
+            let mut req = c1.request((), 1, 0x22, 40);
+            assert!(futures::poll!(&mut req).is_pending());
+            assert_eq!(i.requests.lock().unwrap().len(), 1);
+        });
+        assert!(i.requests.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "faults")]
+    #[test]
+    fn test_wire_duplicate_seeded() {
+        let run = |seed| {
+            let (mut s, mut i) = Sim::new(TestWire::new(unreachable).with_seed(seed));
+            i.set_link(0x11, 0x22, Link{ duplicate: 0.5, ..Default::default() });
+
+            let c1 = i.connector(0x11);
+            let log = s.echo(i.connector(0x22));
+
+            // Duplicates are received by the target, with a single response returned
+            for id in 0..20 {
+                assert_eq!(s.request(&c1, id, 0x22), Some(50));
+            }
+
+            let n = log.lock().unwrap().len();
+            n
+        };
+
+        // Runs are reproducible for a given seed
+        let n = run(7);
+        assert!(n > 20 && n < 40);
+        assert_eq!(run(7), n);
+    }
+
+    #[cfg(feature = "faults")]
+    #[test]
+    fn test_wire_reorder() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+        i.set_link(0x11, 0x22, Link{ reorder: 1.0, reorder_delay: Duration::from_millis(20), ..Default::default() });
+
+        let c1 = i.connector(0x11);
+        let c3 = i.connector(0x33);
+        let log = s.echo(i.connector(0x22));
+
+        // Reordered messages are overtaken by later messages
+        let mut rx = s.start(&c1, 1, 0x22);
+        assert_eq!(s.request(&c3, 2, 0x22), Some(50));
+        assert_eq!(*log.lock().unwrap(), vec![0x33]);
+
+        s.advance(Duration::from_millis(20));
+        assert_eq!(rx.try_recv(), Ok(Some(Ok(50))));
+        assert_eq!(*log.lock().unwrap(), vec![0x33, 0x11]);
+    }
+
+    #[test]
+    fn test_wire_cut() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+
+        let c1 = i.connector(0x11);
+        let c3 = i.connector(0x33);
+        s.echo(i.connector(0x22));
+
+        // Requests over cut links fail immediately
+        i.cut(0x11, 0x22);
+        assert!(!i.is_reachable(&0x22, &0x11));
+        assert_eq!(s.try_request(&c1, 1, 0x22), Some(Err("22 unreachable from 11".to_string())));
+        assert_eq!(s.request(&c3, 2, 0x22), Some(50));
+
+        i.restore(0x11, 0x22);
+        assert_eq!(s.request(&c1, 3, 0x22), Some(50));
+
+        // As do requests to unknown targets
+        assert_eq!(s.try_request(&c1, 4, 0x44), Some(Err("44 unreachable from 11".to_string())));
+    }
+
+    #[test]
+    fn test_wire_cut_one_way() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+
+        let c1 = i.connector(0x11);
+        let c2 = i.connector(0x22);
+        let log = s.echo(c1.clone());
+        s.echo(c2.clone());
+
+        i.cut_one_way(0x11, 0x22);
+        assert!(i.is_reachable(&0x22, &0x11));
+        assert_eq!(s.try_request(&c1, 1, 0x22), Some(Err("22 unreachable from 11".to_string())));
+
+        // Requests in the reverse direction are received, but fail as responses cannot be returned
+        assert_eq!(s.try_request(&c2, 2, 0x11), Some(Err("22 unreachable from 11".to_string())));
+        assert_eq!(*log.lock().unwrap(), vec![0x22]);
+        assert!(i.requests.lock().unwrap().is_empty());
+
+        i.restore_one_way(0x11, 0x22);
+        assert_eq!(s.request(&c2, 3, 0x11), Some(50));
+    }
+
+    #[test]
+    fn test_wire_isolate() {
+        let (mut s, mut i) = Sim::new(TestWire::new(unreachable));
+
+        let c1 = i.connector(0x11);
+        s.echo(i.connector(0x22));
+        s.echo(i.connector(0x33));
+
+        // Isolated targets are unreachable from all others
+        i.isolate(0x22);
+        assert_eq!(s.try_request(&c1, 1, 0x22), Some(Err("22 unreachable from 11".to_string())));
+        assert_eq!(s.request(&c1, 2, 0x33), Some(50));
+
+        i.rejoin(0x22);
+        assert_eq!(s.request(&c1, 3, 0x22), Some(50));
+
+        // Healing restores all links
+        i.isolate(0x22);
+        i.cut(0x11, 0x33);
+        i.heal();
+        assert_eq!(s.request(&c1, 4, 0x22), Some(50));
+        assert_eq!(s.request(&c1, 5, 0x33), Some(50));
+    }
+
+    #[test]
+    fn test_wire_unreachable() {
+        let mut i = TestWire::new(unreachable);
+        let mut c1 = i.connector(0x11);
+
+        // Messages to unknown targets fail with the unreachable error
+        assert_eq!(block_on(c1.request((), 1, 0x44, 40)), Err("44 unreachable from 11".to_string()));
+    }
+}